@@ -1,10 +1,6 @@
 use std::time::Duration;
 
-use crate::{
-    position::Position,
-    search::{search, tt::TT},
-    SearchOptions,
-};
+use crate::{position::Position, Limits, Searcher};
 
 pub const BENCH_FENS: [&str; 50] = [
     "r3k2r/2pb1ppp/2pp1q2/p7/1nP1B3/1P2P3/P2N1PPP/R2QK2R w KQkq a6 0 14",
@@ -60,19 +56,20 @@ pub const BENCH_FENS: [&str; 50] = [
 ];
 
 pub fn bench() {
-    let mut tt = TT::new_default();
-    let mut options = SearchOptions::default();
-    options.depth = 6;
-    options.info = false;
+    let searcher = Searcher::new();
+    let limits = Limits {
+        depth: Some(6),
+        ..Default::default()
+    };
 
     let mut nodes = 0;
     let mut elapsed = Duration::default();
 
     for fen in BENCH_FENS {
-        tt.clear();
-        let pos = Position::from_fen(fen);
+        searcher.clear();
+        let pos = Position::from_fen(fen).unwrap();
 
-        let res = search(&pos, options.clone(), vec![pos.hash()], &mut tt);
+        let res = searcher.search(&pos, &[pos.hash()], limits.clone());
         nodes += res.nodes;
         elapsed += res.elapsed;
         println!(
@@ -1,5 +1,7 @@
 use crate::{bitloop, position::Position};
 
+/// A training position in the 32 byte marlinformat layout. `wdl` is 0 for a black win, 1 for a
+/// draw and 2 for a white win.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PackedBoard {
@@ -19,7 +21,7 @@ unsafe impl bytemuck::Zeroable for PackedBoard {}
 const UNMOVED_ROOK: u8 = 6;
 
 impl PackedBoard {
-    pub fn pack(pos: &Position, fullmove: u16, eval: i32, wdl: u8, extra: u8) -> PackedBoard {
+    pub fn pack(pos: &Position, eval: i32, wdl: u8, extra: u8) -> PackedBoard {
         let occupied = (pos.colors[0] | pos.colors[1]).to_le();
         let mut pieces = 0u128;
 
@@ -38,9 +40,9 @@ impl PackedBoard {
             offset += 4;
         });
 
-        let stm_ep = (pos.side as u8) << 7 | pos.en_passant as u8;
+        let stm_ep = (pos.side as u8) << 7 | pos.en_passant;
         let halfmove = pos.halfmove;
-        let fullmove = fullmove.to_le();
+        let fullmove = pos.fullmove.to_le();
         let eval = (eval.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le();
 
         Self {
@@ -1,4 +1,4 @@
-mod marlinformat;
+pub mod marlinformat;
 
 use std::{
     env::args,
@@ -15,10 +15,10 @@ use std::{
 use chrono::Local;
 
 use crate::{
-    movegen::movegen,
+    movegen::legal_movegen,
     position::{Move, PieceColor, PieceType, Position},
-    search::{is_draw, search, tt::TT, CHECKMATE},
-    SearchOptions,
+    search::{is_draw, CHECKMATE},
+    Limits, Searcher,
 };
 use argh::{EarlyExit, FromArgs};
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
@@ -121,8 +121,6 @@ pub fn datagen() {
     for handle in handles {
         handle.join().unwrap();
     }
-
-    return;
 }
 
 fn generate_games(id: u8, games: u64, folder: String, depth: u8, n_games: Arc<AtomicU64>) {
@@ -146,42 +144,29 @@ fn game(depth: u8) -> Vec<PackedBoard> {
     let mut positions = Vec::with_capacity(256);
 
     // play random moves
-    'rand: for _ in 0..8 {
-        let mut moves = movegen::<true>(&pos);
-        fastrand::shuffle(&mut moves.moves[0..moves.len as usize]);
-        for m in &moves {
-            let p = pos.make_move(*m);
-            if !p.in_check(!p.side) {
-                pos = p;
-                reps.push(pos.hash());
-                continue 'rand;
-            }
+    for _ in 0..8 {
+        let moves = legal_movegen(&pos);
+        if moves.is_empty() {
+            return game(depth);
         }
-        return game(depth);
-    }
 
-    // prevent positions with mate
-    let moves = movegen::<true>(&pos);
-    let mut mate = true;
-    for m in &moves {
-        let p = pos.make_move(*m);
-        if !p.in_check(!p.side) {
-            mate = false;
-            break;
-        }
+        pos = pos.make_move(moves.moves[fastrand::usize(0..moves.len as usize)]);
+        reps.push(pos.hash());
     }
 
-    if mate {
+    // prevent positions with mate
+    if legal_movegen(&pos).is_empty() {
         return game(depth);
     }
 
-    let mut options = SearchOptions::default();
-    options.depth = depth;
-    options.info = false;
+    let limits = Limits {
+        depth: Some(depth),
+        ..Default::default()
+    };
 
-    let mut tt = TT::new_default();
+    let searcher = Searcher::new();
 
-    let mut res = search(&pos, options.clone(), reps.clone(), &mut tt);
+    let mut res = searcher.search(&pos, &reps, limits.clone());
 
     if res.score > 1000 {
         return game(depth);
@@ -189,12 +174,12 @@ fn game(depth: u8) -> Vec<PackedBoard> {
 
     let game_res = loop {
         if !res.best.capture() && res.best.promotion() == PieceType::None {
-            positions.push(PackedBoard::pack(&pos, 0, res.score, 0, 0));
+            positions.push(PackedBoard::pack(&pos, res.score, 0, 0));
         }
         pos = pos.make_move(res.best);
         reps.push(pos.hash());
 
-        res = search(&pos, options.clone(), reps.clone(), &mut tt);
+        res = searcher.search(&pos, &reps, limits.clone());
 
         if res.score.abs() >= CHECKMATE {
             if (res.score.signum() == 1 && pos.side == PieceColor::White)
@@ -215,5 +200,5 @@ fn game(depth: u8) -> Vec<PackedBoard> {
         pos.set_wdl(game_res);
     }
 
-    return positions;
+    positions
 }
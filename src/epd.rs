@@ -0,0 +1,230 @@
+use std::{fs, time::Duration};
+
+use crate::{
+    position::{FenError, Move, Position},
+    Limits, Searcher,
+};
+
+/// A position of an EPD file together with its operations, e.g. `bm`, `am` or `id`
+pub struct Epd {
+    pub pos: Position,
+    pub ops: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    /// Parses an EPD record: the first four FEN fields followed by `;`-terminated operations
+    pub fn parse(line: &str) -> Result<Self, FenError> {
+        let fields: Vec<_> = line.split_whitespace().take(4).collect();
+        let pos = Position::from_fen(&fields.join(" "))?;
+
+        // Everything after the last FEN field, as the fields are slices of `line`
+        let end = fields.last().map_or(0, |field| {
+            field.as_ptr() as usize - line.as_ptr() as usize + field.len()
+        });
+
+        let mut ops = vec![];
+        for op in split_ops(&line[end..]) {
+            let mut operands = split_operands(&op);
+            if operands.is_empty() {
+                continue;
+            }
+
+            let opcode = operands.remove(0);
+            ops.push((opcode, operands));
+        }
+
+        Ok(Self { pos, ops })
+    }
+
+    pub fn op(&self, opcode: &str) -> Option<&[String]> {
+        self.ops
+            .iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    /// Parses the SAN moves of an operation such as `bm` or `am`
+    fn moves(&self, opcode: &str) -> Result<Vec<Move>, String> {
+        self.op(opcode)
+            .unwrap_or_default()
+            .iter()
+            .map(|san| self.pos.parse_san(san).map_err(|e| e.to_string()))
+            .collect()
+    }
+}
+
+/// Splits on `;` outside of string operands
+fn split_ops(s: &str) -> Vec<String> {
+    let mut ops = vec![];
+    let mut op = String::new();
+    let mut quoted = false;
+
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                op.push(c);
+            }
+            ';' if !quoted => ops.push(std::mem::take(&mut op)),
+            c => op.push(c),
+        }
+    }
+    ops.push(op);
+
+    ops.retain(|op| !op.trim().is_empty());
+    ops
+}
+
+/// Splits on whitespace outside of string operands, removing the quotes
+fn split_operands(s: &str) -> Vec<String> {
+    let mut operands = vec![];
+    let mut operand = String::new();
+    let mut quoted = false;
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !operand.is_empty() {
+                    operands.push(std::mem::take(&mut operand));
+                }
+            }
+            c => operand.push(c),
+        }
+    }
+    if !operand.is_empty() {
+        operands.push(operand);
+    }
+
+    operands
+}
+
+/// Runs a tactical test suite: `epd <file> [movetime <ms> | nodes <n>]`
+pub fn epd(args: &[String]) {
+    let Some(path) = args.first() else {
+        eprintln!("usage: epd <file> [movetime <ms> | nodes <n>]");
+        return;
+    };
+
+    let mut limits = Limits {
+        movetime: Some(1000),
+        ..Default::default()
+    };
+
+    match (
+        args.get(1).map(String::as_str),
+        args.get(2).map(|n| n.parse()),
+    ) {
+        (None, _) => {}
+        (Some("movetime"), Some(Ok(ms))) => limits.movetime = Some(ms),
+        (Some("nodes"), Some(Ok(nodes))) => {
+            limits.movetime = None;
+            limits.nodes = Some(nodes);
+        }
+        _ => {
+            eprintln!("usage: epd <file> [movetime <ms> | nodes <n>]");
+            return;
+        }
+    }
+
+    let file = match fs::read_to_string(path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("unable to read {path}: {e}");
+            return;
+        }
+    };
+
+    let searcher = Searcher::new();
+    let mut solved = 0;
+    let mut failed = vec![];
+    let mut elapsed = Duration::default();
+
+    for (i, line) in file.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let epd = match Epd::parse(line) {
+            Ok(epd) => epd,
+            Err(e) => {
+                eprintln!("line {}: {e}", i + 1);
+                continue;
+            }
+        };
+
+        let id = epd
+            .op("id")
+            .and_then(|id| id.first().cloned())
+            .unwrap_or_else(|| format!("line {}", i + 1));
+
+        let (bm, am) = match (epd.moves("bm"), epd.moves("am")) {
+            (Ok(bm), Ok(am)) if !bm.is_empty() || !am.is_empty() => (bm, am),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("{id}: {e}");
+                continue;
+            }
+            _ => {
+                eprintln!("{id}: no bm or am operation");
+                continue;
+            }
+        };
+
+        searcher.clear();
+        let res = searcher.search(&epd.pos, &[epd.pos.hash()], limits.clone());
+        elapsed += res.elapsed;
+
+        let ok = (bm.is_empty() || bm.contains(&res.best)) && !am.contains(&res.best);
+        let expected = bm
+            .iter()
+            .map(|m| epd.pos.move_to_san(*m))
+            .chain(am.iter().map(|m| format!("!{}", epd.pos.move_to_san(*m))))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        println!(
+            "{id}: {} {} (expected {expected}) score {} nodes {}",
+            if ok { "solved" } else { "failed" },
+            epd.pos.move_to_san(res.best),
+            res.score,
+            res.nodes
+        );
+
+        if ok {
+            solved += 1;
+        } else {
+            failed.push(id);
+        }
+    }
+
+    println!(
+        "solved {solved}/{} in {:.1}s",
+        solved + failed.len(),
+        elapsed.as_secs_f64()
+    );
+    if !failed.is_empty() {
+        println!("failed: {}", failed.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Epd;
+
+    #[test]
+    fn parse_epd() {
+        let epd = Epd::parse(
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001; mate";"#,
+        )
+        .unwrap();
+
+        assert_eq!(epd.op("bm"), Some(&["Qg6".to_string()][..]));
+        assert_eq!(epd.op("id"), Some(&["WAC.001; mate".to_string()][..]));
+        assert_eq!(epd.moves("bm").unwrap().len(), 1);
+        assert!(epd.op("am").is_none());
+
+        let epd = Epd::parse("4k3/8/8/8/8/8/8/4K2R  w\tK -  bm O-O;").unwrap();
+        assert_eq!(epd.moves("bm").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,127 @@
+//! bernt is a UCI chess engine. Besides the `bernt` binary, the engine can be used as a library:
+//!
+//! ```
+//! use bernt::{legal_movegen, Limits, Position, Searcher};
+//!
+//! let pos = Position::from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+//! assert_eq!(legal_movegen(&pos).len(), 20);
+//!
+//! let searcher = Searcher::new();
+//! let res = searcher.search(
+//!     &pos,
+//!     &[pos.hash()],
+//!     Limits {
+//!         depth: Some(4),
+//!         ..Default::default()
+//!     },
+//! );
+//! assert_eq!(pos.move_to_san(res.best), "Ra8#");
+//! ```
+//!
+//! - [`Position`] is constructed with [`Position::startpos`] or [`Position::from_fen`] and is
+//!   immutable, [`Position::make_move`] returns the new position.
+//! - [`legal_movegen`] generates the legal moves, [`movegen::movegen`] the pseudo-legal ones.
+//! - [`Searcher`] owns the transposition table and runs searches with [`Limits`].
+//! - [`SearchReporter`] receives the progress of a search, set with [`Searcher::set_reporter`]:
+//!
+//! ```
+//! use std::sync::{
+//!     atomic::{AtomicU8, Ordering},
+//!     Arc,
+//! };
+//!
+//! use bernt::{Limits, Position, SearchInfo, SearchReporter, Searcher};
+//!
+//! struct Depth(Arc<AtomicU8>);
+//!
+//! impl SearchReporter for Depth {
+//!     fn iteration(&self, info: &SearchInfo) {
+//!         self.0.store(info.depth, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let depth = Arc::new(AtomicU8::new(0));
+//! let mut searcher = Searcher::new();
+//! searcher.set_reporter(Depth(depth.clone()));
+//!
+//! let pos = Position::startpos();
+//! searcher.search(
+//!     &pos,
+//!     &[pos.hash()],
+//!     Limits {
+//!         depth: Some(5),
+//!         ..Default::default()
+//!     },
+//! );
+//! assert_eq!(depth.load(Ordering::Relaxed), 5);
+//! ```
+//!
+//! - [`evaluate`] is the static evaluation.
+
+pub mod bench;
+pub mod bitboard;
+#[cfg(feature = "datagen")]
+pub mod datagen;
+pub mod epd;
+pub mod movegen;
+pub mod perft;
+pub mod pgn;
+pub mod position;
+pub mod san;
+pub mod search;
+pub mod searcher;
+pub mod zobrist;
+
+use std::sync::{atomic::AtomicBool, Arc};
+
+pub use crate::{
+    movegen::legal_movegen,
+    position::{FenError, Move, PieceColor, PieceType, Position},
+    search::{eval::evaluate, NullReporter, SearchInfo, SearchReporter, SearchResult},
+    searcher::{Limits, Searcher},
+};
+
+/// Everything a single call to [`search::search`] needs. Embedders should prefer [`Searcher`],
+/// which fills these in from [`Limits`] and its own settings.
+#[derive(Clone)]
+pub struct SearchOptions {
+    pub wtime: i64,
+    pub btime: i64,
+    pub winc: u64,
+    pub binc: u64,
+    pub movestogo: Option<u64>,
+    pub depth: u8,
+    pub movetime: Option<u64>,
+    pub nodes: Option<u64>,
+    pub mate: Option<u8>,
+    pub infinite: bool,
+    pub threads: usize,
+    pub multipv: usize,
+    pub searchmoves: Vec<Move>,
+    pub reporter: Arc<dyn SearchReporter>,
+    pub stop: Arc<AtomicBool>,
+    pub ponder: Arc<AtomicBool>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            wtime: i64::MAX,
+            btime: i64::MAX,
+            movestogo: None,
+            winc: 0,
+            binc: 0,
+            depth: 255,
+            movetime: None,
+            nodes: None,
+            mate: None,
+            infinite: false,
+            threads: 1,
+            multipv: 1,
+            searchmoves: vec![],
+            reporter: Arc::new(NullReporter),
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
@@ -1,21 +1,20 @@
-pub mod bench;
-pub mod bitboard;
-#[cfg(feature = "datagen")]
-pub mod datagen;
-pub mod movegen;
-pub mod perft;
-pub mod position;
-pub mod search;
-pub mod zobrist;
-
-use std::{env, io::stdin, time::Instant};
-
-use crate::{
+use std::{
+    env,
+    io::stdin,
+    iter::Peekable,
+    process,
+    slice::Iter,
+    str::FromStr,
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use bernt::{
     bench::bench,
-    movegen::movegen,
-    perft::split_perft,
-    position::Position,
-    search::{eval::eval, search, tt::TT},
+    bitloop, epd, evaluate, legal_movegen,
+    perft::{self, split_perft, PerftTable},
+    position::format_sq,
+    Limits, Move, Position, SearchInfo, SearchReporter, Searcher,
 };
 
 fn main() {
@@ -24,8 +23,14 @@ fn main() {
     if args.len() >= 2 {
         match args[1].as_str() {
             "bench" => bench(),
+            "epd" => epd::epd(&args[2..]),
+            "perft-suite" => {
+                if !perft::perft_suite(&args[2..]) {
+                    process::exit(1);
+                }
+            }
             #[cfg(feature = "datagen")]
-            "datagen" => datagen::datagen(),
+            "datagen" => bernt::datagen::datagen(),
             _ => {}
         }
         return;
@@ -35,128 +40,201 @@ fn main() {
 
     let mut pos = Position::startpos();
     let mut repetitions = vec![pos.hash()];
-    let mut tt = TT::new_default();
+    let mut searcher = Searcher::new();
+    let mut search_thread = None;
+    let mut chess960 = false;
+    // Kept between runs, entries don't depend on the position they were found from
+    let mut perft_table = None;
+    searcher.set_reporter(UciReporter { chess960 });
 
     loop {
         line.clear();
-        stdin().read_line(&mut line).unwrap();
+        if stdin().read_line(&mut line).unwrap() == 0 {
+            line.push_str("quit");
+        }
 
         let args: Vec<_> = line.split_whitespace().collect();
 
-        if args.len() == 0 {
+        if args.is_empty() {
             continue;
         }
 
         match args[0] {
-            "quit" => return,
+            "quit" => {
+                stop_search(&mut search_thread, &searcher);
+                return;
+            }
+            "stop" => stop_search(&mut search_thread, &searcher),
+            "ponderhit" => searcher.ponderhit(),
             "isready" => println!("readyok"),
             "uci" => {
                 println!("id name bernt");
                 println!("id author GreatGodOfFire");
                 println!("option name Hash type spin default 16 min 1 max 262144");
-                // For OpenBench
-                println!("option name Threads type spin default 1 min 1 max 1");
+                println!("option name Threads type spin default 1 min 1 max 256");
+                println!("option name MultiPV type spin default 1 min 1 max 256");
+                println!("option name Ponder type check default false");
+                println!("option name UCI_Chess960 type check default false");
                 println!("uciok");
             }
             "setoption" => {
+                stop_search(&mut search_thread, &searcher);
                 if let Some(option) = parse_setoption(&args[1..]) {
                     match option {
-                        UciOption::Hash(mb) => tt.set_size(mb),
-                        UciOption::Threads(_) => {}
+                        UciOption::Hash(mb) => {
+                            searcher.set_hash(mb);
+                            perft_table = None;
+                        }
+                        UciOption::Threads(n) => searcher.set_threads(n),
+                        UciOption::MultiPV(n) => searcher.set_multipv(n),
+                        UciOption::Ponder(_) => {}
+                        UciOption::Chess960(enabled) => {
+                            chess960 = enabled;
+                            searcher.set_reporter(UciReporter { chess960 });
+                        }
                     }
                 }
             }
             "ucinewgame" => {
+                stop_search(&mut search_thread, &searcher);
                 pos = Position::startpos();
-                tt = TT::new(tt.size());
+                searcher.clear();
             }
             "perft" => {
-                let depth = args[1].parse().unwrap();
-                let instant = Instant::now();
-                let res = split_perft(&pos, depth);
-                let elapsed = instant.elapsed();
-                println!("Elapsed: {elapsed:?}");
-                println!("Leaf Nodes: {res}");
-                println!(
-                    "Leaf Nodes per second: {}",
-                    (res as f32 / instant.elapsed().as_secs_f32()) as u64
-                );
+                stop_search(&mut search_thread, &searcher);
+                run_perft(&pos, args.get(1), &searcher, &mut perft_table, chess960);
             }
             "position" => {
-                let fen = args[1];
-
-                repetitions = vec![];
+                let moves_start = args
+                    .iter()
+                    .position(|x| *x == "moves")
+                    .unwrap_or(args.len());
+
+                let new_pos = match args.get(1) {
+                    Some(&"startpos") => Ok(Position::startpos()),
+                    Some(&"fen") => Position::from_fen(&args[2..moves_start].join(" ")),
+                    _ => {
+                        eprintln!("expected \"startpos\" or \"fen\" after \"position\"");
+                        continue;
+                    }
+                };
 
-                if fen == "startpos" {
-                    pos = Position::startpos();
+                match new_pos {
+                    Ok(new_pos) => pos = new_pos,
+                    Err(e) => {
+                        eprintln!("invalid fen: {e}");
+                        continue;
+                    }
+                }
 
-                    repetitions.push(pos.hash());
+                repetitions = vec![pos.hash()];
 
-                    for m in &args[2..] {
-                        let moves = movegen::<true>(&pos);
+                for m in args.iter().skip(moves_start + 1) {
+                    let moves = legal_movegen(&pos);
 
-                        for n in &moves {
-                            if n.to_string().as_str() == *m {
-                                pos = pos.make_move(*n);
-                                repetitions.push(pos.hash());
-                                break;
-                            }
-                        }
+                    if let Some(n) = moves
+                        .into_iter()
+                        .find(|n| n.display(chess960).to_string() == *m)
+                    {
+                        pos = pos.make_move(*n);
+                        repetitions.push(pos.hash());
+                    } else {
+                        eprintln!("illegal move {m}");
+                        break;
                     }
-                } else {
-                    let moves_start = args
-                        .iter()
-                        .position(|x| *x == "moves")
-                        .unwrap_or(args.len());
-
-                    let fen = args[2..moves_start].join(" ");
-                    pos = Position::from_fen(&fen);
-
-                    repetitions.push(pos.hash());
-
-                    if moves_start < args.len() {
-                        for m in &args[moves_start + 1..] {
-                            let moves = movegen::<true>(&pos);
-
-                            for n in &moves {
-                                if n.to_string().as_str() == *m {
-                                    pos = pos.make_move(*n);
-                                    repetitions.push(pos.hash());
-                                    break;
-                                }
-                            }
 
-                            repetitions = repetitions
-                                [repetitions.len().saturating_sub(pos.halfmove as usize + 1)..]
-                                .to_vec();
-                        }
-                    }
+                    repetitions = repetitions
+                        [repetitions.len().saturating_sub(pos.halfmove as usize + 1)..]
+                        .to_vec();
                 }
             }
+            "d" => {
+                let mut checkers = vec![];
+                bitloop!(pos.checkers() => sq, {
+                    checkers.push(format_sq(sq));
+                });
+
+                println!("{pos}");
+                println!();
+                println!("Fen: {}", pos.to_fen());
+                println!("Hash: {:016x}", pos.hash());
+                println!("Side to move: {:?}", pos.side);
+                println!("Castling: {}", pos.castling_string());
+                println!("Checkers: {}", checkers.join(" "));
+            }
             "eval" => {
-                println!("Evaluation: {}", eval(&pos).0);
+                println!("Evaluation: {}", evaluate(&pos));
             }
             "go" => {
-                let mut iter = args[1..].iter();
-                let mut options = SearchOptions::default();
+                stop_search(&mut search_thread, &searcher);
+
+                if args.get(1) == Some(&"perft") {
+                    run_perft(&pos, args.get(2), &searcher, &mut perft_table, chess960);
+                    continue;
+                }
+
+                let mut iter = args[1..].iter().peekable();
+                let mut limits = Limits::default();
+                let mut ponder = false;
 
                 while let Some(arg) = iter.next() {
                     match *arg {
-                        "wtime" => options.wtime = iter.next().unwrap().parse().unwrap(),
-                        "btime" => options.btime = iter.next().unwrap().parse().unwrap(),
+                        "wtime" => limits.wtime = go_value(arg, &mut iter).or(limits.wtime),
+                        "btime" => limits.btime = go_value(arg, &mut iter).or(limits.btime),
                         "movestogo" => {
-                            options.movestogo = Some(iter.next().unwrap().parse().unwrap())
+                            limits.movestogo = go_value(arg, &mut iter).or(limits.movestogo)
+                        }
+                        "winc" => limits.winc = go_value(arg, &mut iter).unwrap_or(limits.winc),
+                        "binc" => limits.binc = go_value(arg, &mut iter).unwrap_or(limits.binc),
+                        "depth" => limits.depth = go_value(arg, &mut iter).or(limits.depth),
+                        "movetime" => {
+                            limits.movetime = go_value(arg, &mut iter).or(limits.movetime)
+                        }
+                        "nodes" => limits.nodes = go_value(arg, &mut iter).or(limits.nodes),
+                        "mate" => limits.mate = go_value(arg, &mut iter).or(limits.mate),
+                        "infinite" => limits.infinite = true,
+                        "ponder" => ponder = true,
+                        "searchmoves" => {
+                            let moves = legal_movegen(&pos);
+
+                            while let Some(m) = iter.peek().and_then(|x| {
+                                moves
+                                    .into_iter()
+                                    .find(|m| m.display(chess960).to_string() == **x)
+                            }) {
+                                limits.searchmoves.push(*m);
+                                iter.next();
+                            }
                         }
-                        "winc" => options.winc = iter.next().unwrap().parse().unwrap(),
-                        "binc" => options.binc = iter.next().unwrap().parse().unwrap(),
-                        "depth" => options.depth = iter.next().unwrap().parse().unwrap(),
                         _ => {}
                     }
                 }
 
-                println!(
-                    "bestmove {}",
-                    search(&pos, options, repetitions.clone(), &mut tt).best
+                searcher.reset_signals(ponder);
+
+                let pos = pos.clone();
+                let repetitions = repetitions.clone();
+                let searcher = searcher.clone();
+
+                search_thread = Some(
+                    thread::Builder::new()
+                        .stack_size(8_000_000)
+                        .spawn(move || {
+                            let infinite = limits.infinite;
+                            let res = searcher.search(&pos, &repetitions, limits);
+
+                            // `go infinite` and `go ponder` must not report a move before
+                            // `stop` or `ponderhit`
+                            searcher.wait(infinite);
+
+                            let best = res.best.display(chess960);
+                            if let Some(ponder) = res.pv.get(1) {
+                                println!("bestmove {best} ponder {}", ponder.display(chess960));
+                            } else {
+                                println!("bestmove {best}");
+                            }
+                        })
+                        .unwrap(),
                 );
             }
             _ => {}
@@ -164,40 +242,91 @@ fn main() {
     }
 }
 
-#[derive(Clone)]
-pub struct SearchOptions {
-    pub wtime: i64,
-    pub btime: i64,
-    pub winc: u64,
-    pub binc: u64,
-    pub movestogo: Option<u64>,
-    pub depth: u8,
-    pub info: bool,
+/// Parses the value after a `go` parameter. An invalid or missing value is reported and left in
+/// place, so it can still be read as the next parameter.
+fn go_value<T: FromStr>(name: &str, iter: &mut Peekable<Iter<&str>>) -> Option<T> {
+    match iter.peek().map(|value| value.parse()) {
+        Some(Ok(value)) => {
+            iter.next();
+            Some(value)
+        }
+        Some(Err(_)) => {
+            eprintln!(
+                "unable to parse {} as a value for {name}",
+                iter.peek().unwrap()
+            );
+            None
+        }
+        None => {
+            eprintln!("no value given for {name}");
+            None
+        }
+    }
 }
 
-impl Default for SearchOptions {
-    fn default() -> Self {
-        Self {
-            wtime: i64::MAX,
-            btime: i64::MAX,
-            movestogo: None,
-            winc: 0,
-            binc: 0,
-            depth: 255,
-            info: true,
-        }
+/// Prints the progress of searches as UCI `info` lines. `bestmove` is printed by the search thread
+/// itself, as it has to wait for `stop` or `ponderhit` first.
+struct UciReporter {
+    chess960: bool,
+}
+
+impl SearchReporter for UciReporter {
+    fn iteration(&self, info: &SearchInfo) {
+        println!("{}", info.display(self.chess960));
+    }
+
+    fn currmove(&self, depth: u8, m: Move, number: u16) {
+        println!(
+            "info depth {depth} currmove {} currmovenumber {number}",
+            m.display(self.chess960)
+        );
+    }
+}
+
+/// Runs a hashed perft with a table of the Hash size, allocated on the first run
+fn run_perft(
+    pos: &Position,
+    depth: Option<&&str>,
+    searcher: &Searcher,
+    table: &mut Option<PerftTable>,
+    chess960: bool,
+) {
+    let Some(Ok(depth)) = depth.map(|d| d.parse()) else {
+        eprintln!("usage: go perft <depth>");
+        return;
+    };
+
+    let table = table.get_or_insert_with(|| PerftTable::new(searcher.hash()));
+    let instant = Instant::now();
+    let res = split_perft(pos, depth, searcher.threads(), Some(table), chess960);
+    let elapsed = instant.elapsed();
+    println!("Elapsed: {elapsed:?}");
+    println!("Leaf Nodes: {res}");
+    println!(
+        "Leaf Nodes per second: {}",
+        (res as f32 / elapsed.as_secs_f32()) as u64
+    );
+}
+
+fn stop_search(search_thread: &mut Option<JoinHandle<()>>, searcher: &Searcher) {
+    if let Some(handle) = search_thread.take() {
+        searcher.stop();
+        handle.join().unwrap();
     }
 }
 
 enum UciOption {
     Hash(usize),
-    Threads(u8),
+    Threads(usize),
+    MultiPV(usize),
+    Ponder(bool),
+    Chess960(bool),
 }
 
 fn parse_setoption(args: &[&str]) -> Option<UciOption> {
     use UciOption::*;
 
-    if args.len() == 0 || args[0] != "name" {
+    if args.is_empty() || args[0] != "name" {
         eprintln!("no \"name\" after \"setoption\"");
         return None;
     }
@@ -216,6 +345,15 @@ fn parse_setoption(args: &[&str]) -> Option<UciOption> {
         "Threads" => {
             option = Threads(0);
         }
+        "MultiPV" => {
+            option = MultiPV(0);
+        }
+        "Ponder" => {
+            option = Ponder(false);
+        }
+        "UCI_Chess960" => {
+            option = Chess960(false);
+        }
         o => {
             eprintln!("unknown uci option {o}");
             return None;
@@ -232,16 +370,30 @@ fn parse_setoption(args: &[&str]) -> Option<UciOption> {
         return None;
     }
 
-    if matches!(option, Hash(_) | Threads(_)) {
+    if matches!(option, Hash(_) | Threads(_) | MultiPV(_)) {
         if let Ok(value) = args[3].parse() {
             option = match option {
                 Hash(_) => Hash(value),
-                Threads(_) => Threads(value as u8),
+                Threads(_) => Threads(value),
+                MultiPV(_) => MultiPV(value),
+                Ponder(_) | Chess960(_) => unreachable!(),
             };
         } else {
             eprintln!("unable to parse {} as a number", args[3]);
         }
     }
 
+    if matches!(option, Ponder(_) | Chess960(_)) {
+        if let Ok(value) = args[3].parse() {
+            option = match option {
+                Ponder(_) => Ponder(value),
+                Chess960(_) => Chess960(value),
+                _ => unreachable!(),
+            };
+        } else {
+            eprintln!("unable to parse {} as a boolean", args[3]);
+        }
+    }
+
     Some(option)
 }
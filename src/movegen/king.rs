@@ -5,7 +5,7 @@ use crate::{
 
 use super::{is_attacking, MoveList};
 
-pub fn king_moves<const QUIETS: bool>(
+pub fn king_moves<const CAPTURES: bool, const QUIETS: bool>(
     king: u64,
     empty: u64,
     them: u64,
@@ -20,181 +20,50 @@ pub fn king_moves<const QUIETS: bool>(
         });
     }
 
-    bitloop!(moves & them => to, {
-        movelist += Move::new(from, to, MoveFlag::CAP, PieceType::King);
-    });
+    if CAPTURES {
+        bitloop!(moves & them => to, {
+            movelist += Move::new(from, to, MoveFlag::CAP, PieceType::King);
+        });
+    }
 }
 
 pub fn lookup_king(king: u8) -> u64 {
     LOOKUP[king as usize]
 }
 
+/// Generates castling moves for arbitrary king and rook squares, encoded as the king taking its
+/// own rook. The side to move must not be in check.
 pub fn castling_moves(king: u64, empty: u64, pos: &Position, mut movelist: &mut MoveList) {
     let side = pos.side;
-
-    let rank = (!empty >> (side as u8 * 56)) & 0xff;
     let king_square = king.trailing_zeros() as u8;
 
-    if QUEENSIDE_CASTLE & rank == 0
-        && pos.castling[side][0] != 64
-        && !is_attacking(king_square - 1, pos, !side)
+    for (rook, flag) in pos.castling[side]
+        .into_iter()
+        .zip([MoveFlag::CASTLE_LEFT, MoveFlag::CASTLE_RIGHT])
     {
-        movelist += Move::new(
-            king_square,
-            king_square - 2,
-            MoveFlag::CASTLE_LEFT,
-            PieceType::King,
-        );
-    }
-
-    if KINGSIDE_CASTLE & rank == 0
-        && pos.castling[side][1] != 64
-        && !is_attacking(king_square + 1, pos, !side)
-    {
-        movelist += Move::new(
-            king_square,
-            king_square + 2,
-            MoveFlag::CASTLE_RIGHT,
-            PieceType::King,
-        );
+        if rook == 64 {
+            continue;
+        }
+
+        let m = Move::new(king_square, rook, flag, PieceType::King);
+        let (king_to, rook_to) = m.castling_destinations();
+
+        // Every square the king and the rook cross has to be empty, apart from the two pieces
+        let lo = king_square.min(rook).min(king_to).min(rook_to);
+        let hi = king_square.max(rook).max(king_to).max(rook_to);
+        let span = (u64::MAX >> (63 - hi)) & (u64::MAX << lo);
+        let blockers = span & !empty & !(1 << king_square) & !(1 << rook);
+
+        // The destination of the king is left to the legality check
+        let mut path = (king_square.min(king_to)..=king_square.max(king_to))
+            .filter(|&sq| sq != king_square && sq != king_to);
+
+        if blockers == 0 && path.all(|sq| !is_attacking(sq, pos, !side)) {
+            movelist += m;
+        }
     }
 }
 
-// pub fn frc_castling_moves(
-//     king: u64,
-//     color: PieceColor,
-//     empty: u64,
-//     position: &Position,
-//     movelist: &mut MoveList,
-// ) {
-//     let king_square = king.trailing_zeros() as u8;
-//     let offset = 56 * color as u8;
-//     let rank = ((!(empty | king)) >> offset) & 0xff;
-
-//     let rook = position.castling[color][0];
-//     if rook >= 0
-//         && (rank ^ (1 << (rook % 8)))
-//             & (HORIZONTAL_RAY_BETWEEN[king_square as usize % 8][1]
-//                 | HORIZONTAL_RAY_BETWEEN[rook as usize % 8][4])
-//             == 0
-//     {
-//         let rook = rook as u8;
-//         let diff = king_square - 2 - offset;
-//         if diff == 1 {
-//             movelist.add(Move::new(
-//                 king_square,
-//                 rook,
-//                 MoveType::LeftCastle,
-//                 PieceType::King,
-//             ));
-//         } else if diff == 2 {
-//             if !is_attacking(king_square - 1, position, !color) {
-//                 movelist.add(Move::new(
-//                     king_square,
-//                     rook,
-//                     MoveType::LeftCastle,
-//                     PieceType::King,
-//                 ));
-//             }
-//         } else {
-//             let mut not_attacked = true;
-//             for i in 2 + offset..=king_square - 1 {
-//                 if is_attacking(i, position, !color) {
-//                     not_attacked = false;
-//                     break;
-//                 }
-//             }
-//             if not_attacked {
-//                 movelist.add(Move::new(
-//                     king_square,
-//                     rook,
-//                     MoveType::LeftCastle,
-//                     PieceType::King,
-//                 ));
-//             }
-//         }
-//     }
-
-//     let rook = position.castling[color][1];
-//     if rook >= 0
-//         && (rank ^ (1 << (rook % 8)))
-//             & (HORIZONTAL_RAY_BETWEEN[king_square as usize % 8][7]
-//                 | HORIZONTAL_RAY_BETWEEN[rook as usize % 8][4])
-//             == 0
-//     {
-//         let rook = rook as u8;
-//         let diff = 6 + 56 * color as u8 - king_square;
-//         if diff == 1 {
-//             movelist.add(Move::new(
-//                 king_square,
-//                 rook,
-//                 MoveType::RightCastle,
-//                 PieceType::King,
-//             ));
-//         } else if diff == 2 {
-//             if !is_attacking(king_square + 1, position, !color) {
-//                 movelist.add(Move::new(
-//                     king_square,
-//                     rook,
-//                     MoveType::RightCastle,
-//                     PieceType::King,
-//                 ));
-//             }
-//         } else {
-//             let mut not_attacked = true;
-//             for i in king_square + 1..=6 + offset {
-//                 if is_attacking(i, position, !color) {
-//                     not_attacked = false;
-//                     break;
-//                 }
-//             }
-//             if not_attacked {
-//                 movelist.add(Move::new(
-//                     king_square,
-//                     rook,
-//                     MoveType::RightCastle,
-//                     PieceType::King,
-//                 ));
-//             }
-//         }
-//     }
-// }
-
-const QUEENSIDE_CASTLE: u64 = 0xe;
-const KINGSIDE_CASTLE: u64 = 0x60;
-
-// const HORIZONTAL_RAY_BETWEEN: [[u64; 8]; 8] = generate_horizontal_rays();
-
-// const fn generate_horizontal_rays() -> [[u64; 8]; 8] {
-//     let mut lookup = [[0; 8]; 8];
-
-//     let mut start = 0;
-//     while start < 8 {
-//         let mut end = 0;
-//         while end < 8 {
-//             if start > end {
-//                 let mut i = end + 1;
-//                 while i < start {
-//                     lookup[start][end] |= 1 << i;
-//                     i += 1;
-//                 }
-//             } else {
-//                 let mut i = start + 1;
-//                 while i < end {
-//                     lookup[start][end] |= 1 << i;
-//                     i += 1;
-//                 }
-//             }
-
-//             end += 1;
-//         }
-
-//         start += 1;
-//     }
-
-//     lookup
-// }
-
 const LOOKUP: [u64; 64] = generate_lookup();
 
 const fn generate_lookup() -> [u64; 64] {
@@ -5,7 +5,7 @@ use crate::{
 
 use super::MoveList;
 
-pub fn knight_moves<const QUIETS: bool>(
+pub fn knight_moves<const CAPTURES: bool, const QUIETS: bool>(
     knights: u64,
     free_squares: u64,
     enemies: u64,
@@ -18,9 +18,11 @@ pub fn knight_moves<const QUIETS: bool>(
                 moves += Move::new(from, to, MoveFlag::QUIET, PieceType::Knight);
             });
         }
-        bitloop!(tos & enemies => to, {
-            moves += Move::new(from, to, MoveFlag::CAP, PieceType::Knight);
-        });
+        if CAPTURES {
+            bitloop!(tos & enemies => to, {
+                moves += Move::new(from, to, MoveFlag::CAP, PieceType::Knight);
+            });
+        }
     });
 }
 
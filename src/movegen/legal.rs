@@ -0,0 +1,220 @@
+use crate::{
+    bitloop,
+    position::{Move, MoveFlag, PieceColor, PieceType, Position},
+};
+
+use super::{
+    king::lookup_king,
+    knight::single_knight_moves,
+    movegen, north, single_pawn_attacks,
+    sliding::{single_bishop_moves, single_rook_moves},
+    south, MoveList, RANK_4, RANK_5,
+};
+
+impl Position {
+    /// Returns the enemy pieces giving check to the side to move
+    pub fn checkers(&self) -> u64 {
+        let king = (self.pieces[PieceType::King] & self.colors[self.side]).trailing_zeros() as u8;
+
+        self.attackers_to(king, self.colors[0] | self.colors[1]) & self.colors[!self.side]
+    }
+
+    /// Returns the pieces of the side to move that are pinned to their own king
+    pub fn pinned(&self) -> u64 {
+        use PieceType::*;
+
+        let king = (self.pieces[King] & self.colors[self.side]).trailing_zeros() as u8;
+        let us = self.colors[self.side];
+        let them = self.colors[!self.side];
+        let occupied = us | them;
+
+        let snipers = (single_rook_moves(king, 0, 0) & (self.pieces[Rook] | self.pieces[Queen])
+            | single_bishop_moves(king, 0, 0) & (self.pieces[Bishop] | self.pieces[Queen]))
+            & them;
+
+        let mut pinned = 0;
+        bitloop!(snipers => sniper, {
+            let blockers = between(king, sniper) & occupied;
+            if blockers.count_ones() == 1 {
+                pinned |= blockers & us;
+            }
+        });
+
+        pinned
+    }
+}
+
+/// Generates only the legal moves of the side to move
+pub fn legal_movegen(pos: &Position) -> MoveList {
+    let checkers = pos.checkers();
+
+    if checkers != 0 {
+        return evasions(pos, checkers);
+    }
+
+    let king = (pos.pieces[PieceType::King] & pos.colors[pos.side]).trailing_zeros() as u8;
+    let pinned = pos.pinned();
+    let mut legal = MoveList::new();
+    let mut moves = &mut legal;
+
+    for &m in &movegen::<true>(pos) {
+        if is_legal(pos, m, king, pinned) {
+            moves += m;
+        }
+    }
+
+    legal
+}
+
+/// Generates the legal moves getting out of check: king moves, and if there is only one checker,
+/// captures of the checker and blocks. Pinned pieces can do neither, so they are left out.
+fn evasions(pos: &Position, checkers: u64) -> MoveList {
+    use PieceType::*;
+
+    let king = (pos.pieces[King] & pos.colors[pos.side]).trailing_zeros() as u8;
+    let us = pos.colors[pos.side];
+    let them = pos.colors[!pos.side];
+    let occupied = us | them;
+    let mut legal = MoveList::new();
+    let mut moves = &mut legal;
+
+    // The king is removed from the occupancy, it can't step back along the line of a slider
+    bitloop!(lookup_king(king) & !us => to, {
+        if pos.attackers_to(to, occupied ^ (1 << king)) & them == 0 {
+            moves += Move::new(king, to, capture_flag(them, to), King);
+        }
+    });
+
+    if checkers.count_ones() > 1 {
+        return legal;
+    }
+
+    let checker = checkers.trailing_zeros() as u8;
+    let blocks = between(king, checker);
+    let targets = checkers | blocks;
+    let pieces = us & !pos.pinned();
+
+    bitloop!(pos.pieces[Knight] & pieces => from, {
+        bitloop!(single_knight_moves(from) & targets => to, {
+            moves += Move::new(from, to, capture_flag(them, to), Knight);
+        });
+    });
+    bitloop!(pos.pieces[Bishop] & pieces => from, {
+        bitloop!(single_bishop_moves(from, us, them) & targets => to, {
+            moves += Move::new(from, to, capture_flag(them, to), Bishop);
+        });
+    });
+    bitloop!(pos.pieces[Rook] & pieces => from, {
+        bitloop!(single_rook_moves(from, us, them) & targets => to, {
+            moves += Move::new(from, to, capture_flag(them, to), Rook);
+        });
+    });
+    bitloop!(pos.pieces[Queen] & pieces => from, {
+        let attacks = single_rook_moves(from, us, them) | single_bishop_moves(from, us, them);
+        bitloop!(attacks & targets => to, {
+            moves += Move::new(from, to, capture_flag(them, to), Queen);
+        });
+    });
+
+    let pawns = pos.pieces[Pawn] & pieces;
+
+    bitloop!(pawns & single_pawn_attacks(checker, !pos.side) => from, {
+        pawn_moves(from, checker, MoveFlag::CAP, moves);
+    });
+
+    let (back, double_push_rank): (fn(_) -> _, _) = match pos.side {
+        PieceColor::White => (south, RANK_4),
+        PieceColor::Black => (north, RANK_5),
+    };
+    bitloop!(blocks => to, {
+        let single = back(1 << to);
+        if pawns & single != 0 {
+            pawn_moves(single.trailing_zeros() as u8, to, MoveFlag::QUIET, moves);
+        } else if (1 << to) & double_push_rank != 0
+            && single & occupied == 0
+            && pawns & back(single) != 0
+        {
+            let from = back(single).trailing_zeros() as u8;
+            moves += Move::new(from, to, MoveFlag::DOUBLE_PAWN, Pawn);
+        }
+    });
+
+    // En passant captures the pawn that just moved or blocks on its square. It is played out, as the
+    // captured pawn may uncover an attack along the rank
+    if pos.en_passant != 64
+        && (back(1 << pos.en_passant) == checkers || blocks & (1 << pos.en_passant) != 0)
+    {
+        bitloop!(pawns & single_pawn_attacks(pos.en_passant, !pos.side) => from, {
+            let m = Move::new(from, pos.en_passant, MoveFlag::EP, Pawn);
+            if !pos.make_move(m).in_check(pos.side) {
+                moves += m;
+            }
+        });
+    }
+
+    legal
+}
+
+fn capture_flag(them: u64, to: u8) -> u8 {
+    if them & (1 << to) != 0 {
+        MoveFlag::CAP
+    } else {
+        MoveFlag::QUIET
+    }
+}
+
+/// Adds a pawn move, or all four promotions if it reaches the last rank
+fn pawn_moves(from: u8, to: u8, flag: u8, mut moves: &mut MoveList) {
+    if to >= 56 || to <= 7 {
+        for promotion in 0..4 {
+            moves += Move::new(
+                from,
+                to,
+                flag | MoveFlag::PROMO | promotion,
+                PieceType::Pawn,
+            );
+        }
+    } else {
+        moves += Move::new(from, to, flag, PieceType::Pawn);
+    }
+}
+
+/// Legality of a pseudo-legal move, given the king square and pinned pieces of the side to move
+fn is_legal(pos: &Position, m: Move, king: u8, pinned: u64) -> bool {
+    let occupied = pos.colors[0] | pos.colors[1];
+    let them = pos.colors[!pos.side];
+
+    // The captured pawn may uncover an attack along the rank, and in Chess960 the castling rook
+    // may have been shielding the king's destination, so play these moves out
+    if m.flags == MoveFlag::EP || m.castling() {
+        return !pos.make_move(m).in_check(pos.side);
+    }
+
+    if m.piece == PieceType::King {
+        return pos.attackers_to(m.to, occupied ^ (1 << m.from)) & them == 0;
+    }
+
+    pinned & (1 << m.from) == 0 || line(king, m.from) & (1 << m.to) != 0
+}
+
+/// Squares strictly between two squares on the same rank, file or diagonal
+fn between(a: u8, b: u8) -> u64 {
+    if single_rook_moves(a, 0, 0) & (1 << b) != 0 {
+        single_rook_moves(a, 0, 1 << b) & single_rook_moves(b, 0, 1 << a)
+    } else if single_bishop_moves(a, 0, 0) & (1 << b) != 0 {
+        single_bishop_moves(a, 0, 1 << b) & single_bishop_moves(b, 0, 1 << a)
+    } else {
+        0
+    }
+}
+
+/// The whole rank, file or diagonal through two aligned squares, excluding the squares themselves
+fn line(a: u8, b: u8) -> u64 {
+    if single_rook_moves(a, 0, 0) & (1 << b) != 0 {
+        single_rook_moves(a, 0, 0) & single_rook_moves(b, 0, 0)
+    } else if single_bishop_moves(a, 0, 0) & (1 << b) != 0 {
+        single_bishop_moves(a, 0, 0) & single_bishop_moves(b, 0, 0)
+    } else {
+        0
+    }
+}
@@ -1,5 +1,6 @@
 mod king;
 mod knight;
+mod legal;
 mod sliding;
 
 use std::ops;
@@ -14,6 +15,8 @@ use crate::{
     position::{Move, MoveFlag, PieceColor, PieceType, Position},
 };
 
+pub use self::legal::legal_movegen;
+
 use self::sliding::single_rook_moves;
 
 pub struct MoveList {
@@ -21,6 +24,12 @@ pub struct MoveList {
     pub len: u8,
 }
 
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MoveList {
     pub fn new() -> Self {
         Self {
@@ -32,6 +41,10 @@ impl MoveList {
     pub fn len(&self) -> u8 {
         self.len
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 impl<'a> IntoIterator for &'a MoveList {
@@ -51,6 +64,9 @@ impl ops::AddAssign<Move> for &mut MoveList {
     }
 }
 
+/// Piece values used by the static exchange evaluation, indexed by `PieceType`
+pub const SEE_VALUES: [i32; 7] = [100, 300, 300, 500, 900, 0, 0];
+
 const RANK_2: u64 = 0xff00;
 const RANK_7: u64 = 0xff000000000000;
 
@@ -62,6 +78,212 @@ impl Position {
             !side,
         )
     }
+
+    /// Returns all pieces of both colors attacking `square`, with sliders seeing through
+    /// everything not in `occupied`
+    pub fn attackers_to(&self, square: u8, occupied: u64) -> u64 {
+        use PieceType::*;
+
+        let rooks = self.pieces[Rook] | self.pieces[Queen];
+        let bishops = self.pieces[Bishop] | self.pieces[Queen];
+
+        (rooks & single_rook_moves(square, 0, occupied)
+            | bishops & single_bishop_moves(square, 0, occupied)
+            | self.pieces[Knight] & single_knight_moves(square)
+            | self.pieces[Pawn]
+                & self.colors[PieceColor::White]
+                & single_pawn_attacks(square, PieceColor::Black)
+            | self.pieces[Pawn]
+                & self.colors[PieceColor::Black]
+                & single_pawn_attacks(square, PieceColor::White)
+            | self.pieces[King] & lookup_king(square))
+            & occupied
+    }
+
+    /// Static exchange evaluation: returns whether the exchange sequence started by `m`
+    /// on its target square gains at least `threshold` for the side to move
+    pub fn see(&self, m: Move, threshold: i32) -> bool {
+        use PieceType::*;
+
+        if m.castling() {
+            return threshold <= 0;
+        }
+
+        let captured = match m.flags {
+            MoveFlag::EP => Pawn,
+            _ if m.capture() => self.piece_at(m.to).ty,
+            _ => None,
+        };
+        let promotion = m.promotion();
+
+        let mut value = SEE_VALUES[captured] - threshold;
+        if promotion != None {
+            value += SEE_VALUES[promotion] - SEE_VALUES[Pawn];
+        }
+        if value < 0 {
+            return false;
+        }
+
+        let attacker = if promotion != None {
+            promotion
+        } else {
+            m.piece
+        };
+        value -= SEE_VALUES[attacker];
+        if value >= 0 {
+            return true;
+        }
+
+        let mut occupied = (self.colors[0] | self.colors[1]) ^ (1 << m.from) | (1 << m.to);
+        if m.flags == MoveFlag::EP {
+            occupied ^= match self.side {
+                PieceColor::White => 1 << (m.to - 8),
+                PieceColor::Black => 1 << (m.to + 8),
+            };
+        }
+
+        let rooks = self.pieces[Rook] | self.pieces[Queen];
+        let bishops = self.pieces[Bishop] | self.pieces[Queen];
+
+        let mut attackers = self.attackers_to(m.to, occupied);
+        let mut side = !self.side;
+
+        loop {
+            let ours = attackers & self.colors[side];
+            if ours == 0 {
+                break;
+            }
+
+            let mut ty = Pawn;
+            for t in [Pawn, Knight, Bishop, Rook, Queen, King] {
+                if ours & self.pieces[t] != 0 {
+                    ty = t;
+                    break;
+                }
+            }
+
+            side = !side;
+            value = -value - 1 - SEE_VALUES[ty];
+
+            if value >= 0 {
+                // The king may only recapture if the square is no longer defended
+                if ty == King && attackers & self.colors[side] != 0 {
+                    side = !side;
+                }
+                break;
+            }
+
+            let lsb = ours & self.pieces[ty];
+            occupied ^= lsb & lsb.wrapping_neg();
+
+            if matches!(ty, Pawn | Bishop | Queen) {
+                attackers |= bishops & single_bishop_moves(m.to, 0, occupied);
+            }
+            if matches!(ty, Rook | Queen) {
+                attackers |= rooks & single_rook_moves(m.to, 0, occupied);
+            }
+            attackers &= occupied;
+        }
+
+        side != self.side
+    }
+
+    /// Whether `m` is one of the moves `movegen` would produce in this position, used to
+    /// validate moves that were not generated here, such as transposition table moves or killers
+    pub fn is_pseudolegal(&self, m: Move) -> bool {
+        use PieceType::*;
+
+        if m == Move::NULL || m.piece == None {
+            return false;
+        }
+
+        let from_bit = 1u64 << m.from;
+        let to_bit = 1u64 << m.to;
+        let us = self.colors[self.side];
+        let them = self.colors[!self.side];
+        let occupied = us | them;
+
+        if self.pieces[m.piece] & us & from_bit == 0 {
+            return false;
+        }
+
+        if m.castling() {
+            if m.piece != King || self.in_check(self.side) {
+                return false;
+            }
+
+            let mut moves = MoveList::new();
+            castling_moves(from_bit, !occupied, self, &mut moves);
+            return moves.into_iter().any(|&x| x == m);
+        }
+
+        if us & to_bit != 0 {
+            return false;
+        }
+
+        if m.flags == MoveFlag::EP {
+            return m.piece == Pawn
+                && m.to == self.en_passant
+                && single_pawn_attacks(m.from, self.side) & to_bit != 0;
+        }
+
+        if m.capture() != (them & to_bit != 0) {
+            return false;
+        }
+
+        if m.piece != Pawn {
+            let attacks = match m.piece {
+                Knight => single_knight_moves(m.from),
+                Bishop => single_bishop_moves(m.from, 0, occupied),
+                Rook => single_rook_moves(m.from, 0, occupied),
+                Queen => {
+                    single_rook_moves(m.from, 0, occupied)
+                        | single_bishop_moves(m.from, 0, occupied)
+                }
+                _ => lookup_king(m.from),
+            };
+
+            return m.flags & !MoveFlag::CAP == 0 && attacks & to_bit != 0;
+        }
+
+        let promotion_rank = match self.side {
+            PieceColor::White => RANK_8,
+            PieceColor::Black => RANK_1,
+        };
+        if (m.flags & MoveFlag::PROMO != 0) != (promotion_rank & to_bit != 0) {
+            return false;
+        }
+
+        let forward = match self.side {
+            PieceColor::White => north,
+            PieceColor::Black => south,
+        };
+        let push = forward(from_bit) == to_bit;
+        let capture = single_pawn_attacks(m.from, self.side) & to_bit != 0;
+
+        match m.flags {
+            MoveFlag::QUIET => push,
+            MoveFlag::DOUBLE_PAWN => {
+                let start_rank = match self.side {
+                    PieceColor::White => RANK_2,
+                    PieceColor::Black => RANK_7,
+                };
+
+                from_bit & start_rank != 0
+                    && forward(from_bit) & occupied == 0
+                    && forward(forward(from_bit)) == to_bit
+            }
+            MoveFlag::CAP => capture,
+            f if f & !0b11 == MoveFlag::PROMO => push,
+            f if f & !0b11 == MoveFlag::CAP | MoveFlag::PROMO => capture,
+            _ => false,
+        }
+    }
+
+    /// Whether the pseudo-legal move `m` leaves our king out of check
+    pub fn is_legal(&self, m: Move) -> bool {
+        !self.make_move(m).in_check(self.side)
+    }
 }
 
 fn is_attacking(square: u8, pos: &Position, side: PieceColor) -> bool {
@@ -123,7 +345,75 @@ pub const fn west(x: u64) -> u64 {
 pub const FILE_A: u64 = 0x101010101010101;
 pub const FILE_H: u64 = 0x8080808080808080;
 
+/// Generates the pseudo-legal moves, only the captures unless `QUIETS` is set
+///
+/// ```
+/// use bernt::{movegen::movegen, Position};
+///
+/// let pos = Position::from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+/// assert_eq!(movegen::<true>(&pos).len(), 7);
+/// assert_eq!(movegen::<false>(&pos).len(), 1);
+/// ```
 pub fn movegen<const QUIETS: bool>(pos: &Position) -> MoveList {
+    generate::<true, QUIETS>(pos)
+}
+
+/// Generates only the pseudo-legal non-captures, including quiet promotions and castling
+pub fn quiet_movegen(pos: &Position) -> MoveList {
+    generate::<false, true>(pos)
+}
+
+/// Counts the pseudo-legal moves of the side to move without generating them, castling is not
+/// included
+pub fn mobility(pos: &Position) -> u32 {
+    use PieceType::*;
+
+    let occupied = pos.colors[0] | pos.colors[1];
+    let empty = !occupied;
+    let us = pos.colors[pos.side as usize];
+    let them = pos.colors[!pos.side as usize];
+
+    let mut count = 0;
+
+    bitloop!(pos.pieces[Queen] & us => from, {
+        count += (single_rook_moves(from, us, them) | single_bishop_moves(from, us, them))
+            .count_ones();
+    });
+    bitloop!(pos.pieces[Rook] & us => from, {
+        count += single_rook_moves(from, us, them).count_ones();
+    });
+    bitloop!(pos.pieces[Bishop] & us => from, {
+        count += single_bishop_moves(from, us, them).count_ones();
+    });
+    bitloop!(pos.pieces[Knight] & us => from, {
+        count += (single_knight_moves(from) & !us).count_ones();
+    });
+    count += (lookup_king((pos.pieces[King] & us).trailing_zeros() as u8) & !us).count_ones();
+
+    let pawns = pos.pieces[Pawn] & us;
+    let (fw, promo_rank, double_push_rank): (fn(_) -> _, _, _) = match pos.side {
+        PieceColor::White => (north, RANK_8, RANK_4),
+        PieceColor::Black => (south, RANK_1, RANK_5),
+    };
+
+    let push = fw(pawns) & empty;
+    let double_push = fw(push) & empty & double_push_rank;
+    let left = east(fw(pawns)) & them;
+    let right = west(fw(pawns)) & them;
+
+    for targets in [push, left, right] {
+        count += (targets & !promo_rank).count_ones() + 4 * (targets & promo_rank).count_ones();
+    }
+    count += double_push.count_ones();
+
+    if pos.en_passant != 64 {
+        count += (pawns & single_pawn_attacks(pos.en_passant, !pos.side)).count_ones();
+    }
+
+    count
+}
+
+fn generate<const CAPTURES: bool, const QUIETS: bool>(pos: &Position) -> MoveList {
     use PieceType::*;
 
     let mut moves = MoveList::new();
@@ -133,17 +423,17 @@ pub fn movegen<const QUIETS: bool>(pos: &Position) -> MoveList {
     let us = pos.colors[pos.side as usize];
     let them = pos.colors[!pos.side as usize];
 
-    queen_moves::<QUIETS>(pos.pieces[Queen] & us, us, them, &mut moves);
-    rook_moves::<QUIETS>(pos.pieces[Rook] & us, us, them, &mut moves);
-    bishop_moves::<QUIETS>(pos.pieces[Bishop] & us, us, them, &mut moves);
+    queen_moves::<CAPTURES, QUIETS>(pos.pieces[Queen] & us, us, them, &mut moves);
+    rook_moves::<CAPTURES, QUIETS>(pos.pieces[Rook] & us, us, them, &mut moves);
+    bishop_moves::<CAPTURES, QUIETS>(pos.pieces[Bishop] & us, us, them, &mut moves);
 
-    knight_moves::<QUIETS>(pos.pieces[Knight] & us, !us, them, &mut moves);
+    knight_moves::<CAPTURES, QUIETS>(pos.pieces[Knight] & us, !us, them, &mut moves);
 
-    if !pos.in_check(pos.side) && QUIETS {
+    if QUIETS && !pos.in_check(pos.side) {
         castling_moves(pos.pieces[King] & us, empty, pos, &mut moves);
     }
 
-    pawn_moves::<QUIETS>(
+    pawn_moves::<CAPTURES, QUIETS>(
         pos.pieces[Pawn] & us,
         empty,
         them,
@@ -151,7 +441,7 @@ pub fn movegen<const QUIETS: bool>(pos: &Position) -> MoveList {
         pos.side,
         &mut moves,
     );
-    king_moves::<QUIETS>(pos.pieces[King] & us, empty, them, &mut moves);
+    king_moves::<CAPTURES, QUIETS>(pos.pieces[King] & us, empty, them, &mut moves);
 
     moves
 }
@@ -164,7 +454,7 @@ fn shift(side: PieceColor, bitboard: u64, by: u8) -> u64 {
     }
 }
 
-fn pawn_moves<const QUIETS: bool>(
+fn pawn_moves<const CAPTURES: bool, const QUIETS: bool>(
     pawns: u64,
     empty: u64,
     them: u64,
@@ -203,6 +493,10 @@ fn pawn_moves<const QUIETS: bool>(
         });
     }
 
+    if !CAPTURES {
+        return;
+    }
+
     let (left_mask, right_mask) = match side {
         PieceColor::White => (!FILE_A, !FILE_H),
         PieceColor::Black => (!FILE_H, !FILE_A),
@@ -242,25 +536,30 @@ fn pawn_moves<const QUIETS: bool>(
         };
         let ep_pawns = pawns & ep_squares & ep_rank;
         bitloop!(ep_pawns => from, {
-            moves += Move::new(from, en_passant as u8, MoveFlag::EP, Pawn);
+            moves += Move::new(from, en_passant, MoveFlag::EP, Pawn);
         });
     }
 }
 
+pub const RANK_1: u64 = 0xff;
 pub const RANK_4: u64 = 0xff << 24;
 pub const RANK_5: u64 = 0xff << 32;
+pub const RANK_8: u64 = 0xff << 56;
 
 #[cfg(test)]
 mod tests {
-    use crate::{perft::split_perft, position::Position};
+    use crate::{
+        perft::{split_perft, PerftTable},
+        position::Position,
+    };
 
     macro_rules! test_perft {
         ($name:ident($fen:literal, $depth:literal) = $res:literal) => {
             #[test]
             fn $name() {
-                let pos = Position::from_fen($fen);
+                let pos = Position::from_fen($fen).unwrap();
 
-                assert_eq!(split_perft(&pos, $depth), $res)
+                assert_eq!(split_perft(&pos, $depth, 1, None, false), $res)
             }
         };
         ($name:ident(startpos, $depth:literal) = $res:literal) => {
@@ -273,7 +572,42 @@ mod tests {
         };
     }
 
+    #[test]
+    fn see() {
+        use crate::position::{Move, MoveFlag, PieceType};
+
+        // Rook takes a pawn defended by a pawn, and a pawn takes an undefended knight
+        let pos = Position::from_fen("4k3/8/2p5/3p4/4P3/8/3R4/4K3 w - - 0 1").unwrap();
+        assert!(!pos.see(Move::new(11, 35, MoveFlag::CAP, PieceType::Rook), 0));
+        assert!(pos.see(Move::new(28, 35, MoveFlag::CAP, PieceType::Pawn), 0));
+
+        // Knight takes a pawn on a file with two stacked rooks of each side
+        let pos = Position::from_fen("3rk3/3r4/8/3p4/8/2N5/3R4/3RK3 w - - 0 1").unwrap();
+        assert!(pos.see(Move::new(18, 35, MoveFlag::CAP, PieceType::Knight), 100));
+        assert!(!pos.see(Move::new(18, 35, MoveFlag::CAP, PieceType::Knight), 101));
+    }
+
+    #[test]
+    fn pseudolegal() {
+        use super::movegen;
+        use crate::position::{Move, MoveFlag, PieceType};
+
+        let pos = Position::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        for m in &movegen::<true>(&pos) {
+            assert!(pos.is_pseudolegal(*m));
+        }
+
+        // Blocked slider, capture of an own piece, double push from the wrong rank
+        assert!(!pos.is_pseudolegal(Move::new(0, 24, MoveFlag::QUIET, PieceType::Rook)));
+        assert!(!pos.is_pseudolegal(Move::new(21, 13, MoveFlag::CAP, PieceType::Queen)));
+        assert!(!pos.is_pseudolegal(Move::new(28, 44, MoveFlag::DOUBLE_PAWN, PieceType::Pawn)));
+    }
+
     test_perft!(startpos_d6(startpos, 6) = 119060324);
+
     test_perft!(
         kiwipete_d5(
             "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
@@ -294,4 +628,46 @@ mod tests {
             5
         ) = 164075551
     );
+
+    test_perft!(
+        frc_d5(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            5
+        ) = 8146062
+    );
+    // Cross-checked against the perft of shakmaty 0.27 in Chess960 mode
+    test_perft!(
+        dfrc_startpos_d5(
+            "rqbbknrn/pppppppp/8/8/8/8/PPPPPPPP/NRBBKQRN w GBga - 0 1",
+            5
+        ) = 3543346
+    );
+    test_perft!(
+        dfrc_middlegame_d4(
+            "r3k1r1/pppq1ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPPQ1PPP/1R2K2R w HBga - 0 1",
+            4
+        ) = 1758707
+    );
+    test_perft!(dfrc_adjacent_d5("1r2k2r/8/8/8/8/8/8/R1K3R1 w GAhb - 0 1", 5) = 7119027);
+    test_perft!(dfrc_king_on_g_d5("r5kr/8/8/8/8/8/8/R5KR w HAha - 0 1", 5) = 5651372);
+    test_perft!(dfrc_rook_shield_d5("4k3/8/8/8/8/8/8/rR2K3 w B - 0 1", 5) = 353895);
+
+    #[test]
+    fn hashed_threaded_perft() {
+        let pos =
+            Position::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -")
+                .unwrap();
+        let table = PerftTable::new(16);
+
+        assert_eq!(split_perft(&pos, 5, 4, Some(&table), false), 193690690);
+        assert_eq!(split_perft(&pos, 5, 4, Some(&table), false), 193690690);
+    }
+
+    #[test]
+    fn xfen_castling() {
+        assert_eq!(
+            Position::from_fen("4k3/8/8/8/8/8/8/1R2K1R1 w KQ - 0 1").unwrap(),
+            Position::from_fen("4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1").unwrap()
+        );
+    }
 }
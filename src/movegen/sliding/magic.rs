@@ -21,8 +21,8 @@ impl Magic {
 
 pub const ROOK_MAGICS: [Magic; 64] = magics(_ROOK_MAGICS, _ROOK_MASKS);
 pub const BISHOP_MAGICS: [Magic; 64] = magics(_BISHOP_MAGICS, _BISHOP_MASKS);
-pub const ROOK_ATTACKS: [u64; attack_count(_ROOK_MASKS_BITS)] = rook_attacks();
-pub const BISHOP_ATTACKS: [u64; attack_count(_BISHOP_MASKS_BITS)] = bishop_attacks();
+pub static ROOK_ATTACKS: [u64; attack_count(_ROOK_MASKS_BITS)] = rook_attacks();
+pub static BISHOP_ATTACKS: [u64; attack_count(_BISHOP_MASKS_BITS)] = bishop_attacks();
 
 const fn attack_count(bits: [u8; 64]) -> usize {
     let mut i = 0;
@@ -9,7 +9,7 @@ use self::magic::{BISHOP_ATTACKS, BISHOP_MAGICS, ROOK_ATTACKS, ROOK_MAGICS};
 
 use super::MoveList;
 
-pub fn queen_moves<const QUIETS: bool>(
+pub fn queen_moves<const CAPTURES: bool, const QUIETS: bool>(
     queens: u64,
     us: u64,
     them: u64,
@@ -24,13 +24,20 @@ pub fn queen_moves<const QUIETS: bool>(
                 movelist += Move::new(queen, to, MoveFlag::QUIET, PieceType::Queen);
             });
         }
-        bitloop!(moves & them => to, {
-            movelist += Move::new(queen, to, MoveFlag::CAP, PieceType::Queen);
-        });
+        if CAPTURES {
+            bitloop!(moves & them => to, {
+                movelist += Move::new(queen, to, MoveFlag::CAP, PieceType::Queen);
+            });
+        }
     });
 }
 
-pub fn rook_moves<const QUIETS: bool>(rooks: u64, us: u64, them: u64, mut movelist: &mut MoveList) {
+pub fn rook_moves<const CAPTURES: bool, const QUIETS: bool>(
+    rooks: u64,
+    us: u64,
+    them: u64,
+    mut movelist: &mut MoveList,
+) {
     bitloop!(rooks => rook, {
         let moves = single_rook_moves(rook, us, them);
 
@@ -39,9 +46,11 @@ pub fn rook_moves<const QUIETS: bool>(rooks: u64, us: u64, them: u64, mut moveli
                 movelist += Move::new(rook, to, MoveFlag::QUIET, PieceType::Rook);
             });
         }
-        bitloop!(moves & them => to, {
-            movelist += Move::new(rook, to, MoveFlag::CAP, PieceType::Rook);
-        });
+        if CAPTURES {
+            bitloop!(moves & them => to, {
+                movelist += Move::new(rook, to, MoveFlag::CAP, PieceType::Rook);
+            });
+        }
     });
 }
 
@@ -55,7 +64,7 @@ pub fn single_rook_moves(rook: u8, us: u64, them: u64) -> u64 {
         & !us
 }
 
-pub fn bishop_moves<const QUIETS: bool>(
+pub fn bishop_moves<const CAPTURES: bool, const QUIETS: bool>(
     bishops: u64,
     us: u64,
     them: u64,
@@ -69,9 +78,11 @@ pub fn bishop_moves<const QUIETS: bool>(
                 movelist += Move::new(bishop, to, MoveFlag::QUIET, PieceType::Bishop);
             });
         }
-        bitloop!(moves & them => to, {
-            movelist += Move::new(bishop, to, MoveFlag::CAP, PieceType::Bishop);
-        });
+        if CAPTURES {
+            bitloop!(moves & them => to, {
+                movelist += Move::new(bishop, to, MoveFlag::CAP, PieceType::Bishop);
+            });
+        }
     });
 }
 
@@ -1,40 +1,289 @@
-use crate::{movegen::movegen, position::Position};
+use std::{
+    fs, mem,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    thread,
+};
 
-pub fn split_perft(pos: &Position, depth: u8) -> u64 {
+use crate::{
+    movegen::{legal_movegen, movegen},
+    position::{Move, Position},
+};
+
+/// Perft results shared between threads without locking. The key word is XORed with the data
+/// word, so a torn write fails verification on lookup.
+pub struct PerftTable(Vec<PerftSlot>);
+
+#[derive(Default)]
+struct PerftSlot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl PerftTable {
+    /// Creates a table of `size` MB, the unit of the transposition table
+    pub fn new(size: usize) -> Self {
+        let len = (size * 1000000 / mem::size_of::<PerftSlot>()).max(1);
+
+        Self((0..len).map(|_| PerftSlot::default()).collect())
+    }
+
+    fn get(&self, hash: u64, depth: u8) -> Option<u64> {
+        let slot = &self.0[hash as usize % self.0.len()];
+        let data = slot.data.load(Ordering::Relaxed);
+
+        (slot.key.load(Ordering::Relaxed) ^ data == hash && data as u8 == depth)
+            .then_some(data >> 8)
+    }
+
+    fn insert(&self, hash: u64, depth: u8, nodes: u64) {
+        let slot = &self.0[hash as usize % self.0.len()];
+        let data = nodes << 8 | depth as u64;
+
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// Prints the node count below every root move and returns the total. Root moves are split
+/// between `threads` threads, and castling is printed as the king taking its rook if `chess960` is
+/// set.
+pub fn split_perft(
+    pos: &Position,
+    depth: u8,
+    threads: usize,
+    table: Option<&PerftTable>,
+    chess960: bool,
+) -> u64 {
     if depth == 0 {
         return 1;
     }
 
-    let mut i = 0;
+    let moves: Vec<_> = legal_movegen(pos).into_iter().copied().collect();
+    let next = AtomicUsize::new(0);
+    let results: Vec<_> = moves.iter().map(|_| AtomicU64::new(0)).collect();
 
-    for m in &movegen::<true>(pos) {
-        let pos = pos.make_move(*m);
+    thread::scope(|s| {
+        for _ in 0..threads.clamp(1, moves.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(m) = moves.get(i) else {
+                    break;
+                };
 
-        if !pos.in_check(!pos.side) {
-            let res = perft(&pos, depth - 1);
-            println!("{m}: {res}");
-            i += res;
+                let nodes = perft(&pos.make_move(*m), depth - 1, table);
+                results[i].store(nodes, Ordering::Relaxed);
+            });
         }
+    });
+
+    let mut i = 0;
+
+    for (m, res) in moves.iter().zip(&results) {
+        let res = res.load(Ordering::Relaxed);
+        println!("{}: {res}", m.display(chess960));
+        i += res;
     }
 
     i
 }
 
-fn perft(pos: &Position, depth: u8) -> u64 {
+pub fn perft(pos: &Position, depth: u8, table: Option<&PerftTable>) -> u64 {
     if depth == 0 {
         return 1;
     }
 
+    // Bulk counting: the leaves don't have to be made
+    if depth == 1 {
+        return legal_movegen(pos).len() as u64;
+    }
+
+    if let Some(nodes) = table.and_then(|t| t.get(pos.hash(), depth)) {
+        return nodes;
+    }
+
     let mut i = 0;
 
-    for m in &movegen::<true>(pos) {
-        let pos = pos.make_move(*m);
+    for m in &legal_movegen(pos) {
+        i += perft(&pos.make_move(*m), depth - 1, table);
+    }
 
-        if !pos.in_check(!pos.side) {
-            let res = perft(&pos, depth - 1);
-            i += res;
-        }
+    if let Some(table) = table {
+        table.insert(pos.hash(), depth, i);
     }
 
     i
 }
+
+/// Legal moves found by playing out every pseudo-legal move, independent of the pin and check
+/// logic of `legal_movegen`
+fn reference_moves(pos: &Position) -> Vec<Move> {
+    movegen::<true>(pos)
+        .into_iter()
+        .copied()
+        .filter(|m| pos.is_legal(*m))
+        .collect()
+}
+
+fn reference_perft(pos: &Position, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    reference_moves(pos)
+        .into_iter()
+        .map(|m| reference_perft(&pos.make_move(m), depth - 1))
+        .sum()
+}
+
+/// Runs a perftsuite EPD file (`<fen> ;D1 20 ;D2 400 ...`): `perft-suite <file> [max depth]`.
+/// Returns whether every position matched.
+pub fn perft_suite(args: &[String]) -> bool {
+    let Some(path) = args.first() else {
+        eprintln!("usage: perft-suite <file> [max depth]");
+        return false;
+    };
+
+    let max_depth = match args.get(1).map(|d| d.parse()) {
+        None => u8::MAX,
+        Some(Ok(depth)) => depth,
+        Some(Err(_)) => {
+            eprintln!("usage: perft-suite <file> [max depth]");
+            return false;
+        }
+    };
+
+    let file = match fs::read_to_string(path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("unable to read {path}: {e}");
+            return false;
+        }
+    };
+
+    let mut passed = 0;
+    let mut failed = 0;
+
+    for (i, line) in file.lines().enumerate() {
+        let mut fields = line.split(';');
+        let fen = fields.next().unwrap_or_default().trim();
+        if fen.is_empty() {
+            continue;
+        }
+
+        let ok = match Position::from_fen(fen) {
+            Ok(pos) => check_position(&pos, fields, max_depth),
+            Err(e) => {
+                println!("line {}: {e}", i + 1);
+                false
+            }
+        };
+
+        if ok {
+            passed += 1;
+            println!("ok {fen}");
+        } else {
+            failed += 1;
+            println!("FAILED {fen}");
+        }
+    }
+
+    println!("{passed} passed, {failed} failed");
+
+    failed == 0
+}
+
+fn check_position<'a>(
+    pos: &Position,
+    depths: impl Iterator<Item = &'a str>,
+    max_depth: u8,
+) -> bool {
+    for field in depths.filter(|field| !field.trim().is_empty()) {
+        let parsed = field.trim().split_once(' ').and_then(|(depth, nodes)| {
+            Some((
+                depth.strip_prefix('D')?.parse::<u8>().ok()?,
+                nodes.trim().parse::<u64>().ok()?,
+            ))
+        });
+
+        let Some((depth, expected)) = parsed else {
+            println!("  invalid depth entry \"{}\"", field.trim());
+            return false;
+        };
+
+        if depth > max_depth {
+            continue;
+        }
+
+        let nodes = perft(pos, depth, None);
+        if nodes != expected {
+            println!("  depth {depth}: expected {expected}, got {nodes}");
+            diff(pos, depth, &mut vec![]);
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Follows the first subtree whose count differs from the reference movegen, until a position
+/// where the generated moves themselves differ
+fn diff(pos: &Position, depth: u8, path: &mut Vec<Move>) {
+    let legal: Vec<_> = legal_movegen(pos).into_iter().copied().collect();
+    let reference = reference_moves(pos);
+
+    let missing: Vec<_> = reference.iter().filter(|m| !legal.contains(m)).collect();
+    let extra: Vec<_> = legal.iter().filter(|m| !reference.contains(m)).collect();
+
+    if !missing.is_empty() || !extra.is_empty() {
+        println!("  after [{}] ({})", format_line(path), pos.to_fen());
+        for m in missing {
+            println!("    missing {m}");
+        }
+        for m in extra {
+            println!("    illegal {m}");
+        }
+        return;
+    }
+
+    if depth > 1 {
+        for &m in &legal {
+            let child = pos.make_move(m);
+            if perft(&child, depth - 1, None) != reference_perft(&child, depth - 1) {
+                path.push(m);
+                return diff(&child, depth - 1, path);
+            }
+        }
+    }
+
+    // Both agree, so the error is either in the pseudo-legal generation they share or in the suite
+    println!(
+        "  legal movegen agrees with the reference below [{}], divide:",
+        format_line(path)
+    );
+    for m in legal {
+        println!("    {m}: {}", perft(&pos.make_move(m), depth - 1, None));
+    }
+}
+
+fn format_line(path: &[Move]) -> String {
+    path.iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_position;
+    use crate::position::Position;
+
+    #[test]
+    fn suite_line() {
+        let line = "4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;D1 15 ;D2 66 ; ";
+        let mut fields = line.split(';');
+        let pos = Position::from_fen(fields.next().unwrap().trim()).unwrap();
+
+        assert!(check_position(&pos, fields, u8::MAX));
+        assert!(!check_position(&pos, "D1 15;D2 67".split(';'), u8::MAX));
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    fmt,
+    io::{self, BufRead},
+};
+
+use crate::{
+    position::{FenError, Move, PieceColor, Position},
+    san::SanError,
+};
+
+/// A game read from or written to PGN. Only the mainline is kept, comments, NAGs and
+/// variations are dropped while reading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub start: Position,
+    pub moves: Vec<Move>,
+    pub result: String,
+}
+
+#[derive(Debug)]
+pub enum PgnError {
+    Io(io::Error),
+    Fen(FenError),
+    San(SanError),
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Fen(e) => write!(f, "invalid FEN tag: {e}"),
+            Self::San(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Fen(e) => Some(e),
+            Self::San(e) => Some(e),
+        }
+    }
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Tags every PGN game has to carry, in the order they are exported
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+impl Game {
+    pub fn new(start: Position) -> Self {
+        Self {
+            tags: vec![],
+            start,
+            moves: vec![],
+            result: "*".to_string(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets a tag, replacing its previous value
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        if let Some(tag) = self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            tag.1 = value.to_string();
+        } else {
+            self.tags.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    /// The position after all moves of the game
+    pub fn end(&self) -> Position {
+        self.moves
+            .iter()
+            .fold(self.start.clone(), |pos, m| pos.make_move(*m))
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => Some(self.result.as_str()),
+                _ => self.tag(name),
+            };
+            writeln!(f, "[{name} \"{}\"]", escape(value.unwrap_or("?")))?;
+        }
+
+        let fen = self.start.to_fen();
+        if self.start != Position::startpos() && self.tag("FEN").is_none() {
+            writeln!(f, "[SetUp \"1\"]")?;
+            writeln!(f, "[FEN \"{fen}\"]")?;
+        }
+
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) {
+                writeln!(f, "[{name} \"{}\"]", escape(value))?;
+            }
+        }
+        writeln!(f)?;
+
+        let mut tokens = vec![];
+        let mut pos = self.start.clone();
+        for (i, m) in self.moves.iter().enumerate() {
+            if pos.side == PieceColor::White {
+                tokens.push(format!("{}.", pos.fullmove));
+            } else if i == 0 {
+                tokens.push(format!("{}...", pos.fullmove));
+            }
+
+            tokens.push(pos.move_to_san(*m));
+            pos = pos.make_move(*m);
+        }
+        tokens.push(self.result.clone());
+
+        // Export format lines are limited to 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                writeln!(f, "{line}")?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{line}")
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Reads games one at a time from a PGN stream
+pub struct PgnReader<R> {
+    reader: R,
+    line: Vec<char>,
+    idx: usize,
+    /// Tag read past the end of a game without a result, the first token of the next game
+    pending_tag: Option<Token>,
+}
+
+enum Token {
+    Tag(String, String),
+    Symbol(String),
+    VariationStart,
+    VariationEnd,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: vec![],
+            idx: 0,
+            pending_tag: None,
+        }
+    }
+
+    fn next_char(&mut self) -> io::Result<Option<char>> {
+        while self.idx >= self.line.len() {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+
+            // Lines starting with `%` are escaped and ignored
+            if line.starts_with('%') {
+                continue;
+            }
+
+            self.line = line.chars().collect();
+            self.idx = 0;
+        }
+
+        self.idx += 1;
+        Ok(Some(self.line[self.idx - 1]))
+    }
+
+    fn peek_char(&mut self) -> io::Result<Option<char>> {
+        let c = self.next_char()?;
+        if c.is_some() {
+            self.idx -= 1;
+        }
+        Ok(c)
+    }
+
+    fn next_token(&mut self) -> io::Result<Option<Token>> {
+        loop {
+            let Some(c) = self.next_char()? else {
+                return Ok(None);
+            };
+
+            match c {
+                c if c.is_whitespace() => {}
+                '{' => while !matches!(self.next_char()?, Some('}') | None) {},
+                ';' => {
+                    self.idx = self.line.len();
+                }
+                '(' => return Ok(Some(Token::VariationStart)),
+                ')' => return Ok(Some(Token::VariationEnd)),
+                '[' => return self.tag().map(Some),
+                '$' => {
+                    while self.peek_char()?.is_some_and(|c| c.is_ascii_digit()) {
+                        self.next_char()?;
+                    }
+                }
+                _ => {
+                    let mut symbol = c.to_string();
+                    while let Some(c) = self.peek_char()? {
+                        if c.is_whitespace() || "{};()[$".contains(c) {
+                            break;
+                        }
+                        symbol.push(c);
+                        self.next_char()?;
+                    }
+
+                    return Ok(Some(Token::Symbol(symbol)));
+                }
+            }
+        }
+    }
+
+    fn tag(&mut self) -> io::Result<Token> {
+        let mut name = String::new();
+        let mut value = String::new();
+
+        while let Some(c) = self.next_char()? {
+            match c {
+                '"' => break,
+                ']' => return Ok(Token::Tag(name.trim().to_string(), value)),
+                c => name.push(c),
+            }
+        }
+
+        while let Some(c) = self.next_char()? {
+            match c {
+                '\\' => value.extend(self.next_char()?),
+                '"' => break,
+                c => value.push(c),
+            }
+        }
+
+        while !matches!(self.next_char()?, Some(']') | None) {}
+
+        Ok(Token::Tag(name.trim().to_string(), value))
+    }
+
+    fn read_game(&mut self) -> Result<Option<Game>, PgnError> {
+        let mut game = Game::new(Position::startpos());
+        let mut pos = game.start.clone();
+        let mut error = None;
+        let mut depth = 0;
+        let mut started = false;
+
+        loop {
+            let token = match self.pending_tag.take() {
+                Some(tag) => Some(tag),
+                None => self.next_token().map_err(PgnError::Io)?,
+            };
+            let token = match token {
+                Some(token) => token,
+                None if started => break,
+                None => return Ok(None),
+            };
+            started = true;
+
+            match token {
+                Token::Tag(name, value) => {
+                    if !game.moves.is_empty() {
+                        // A game without a result followed by the next game's tags, the tag is
+                        // kept for the next game
+                        self.pending_tag = Some(Token::Tag(name, value));
+                        break;
+                    }
+
+                    if name == "FEN" {
+                        match Position::from_fen(&value) {
+                            Ok(start) => {
+                                game.start = start.clone();
+                                pos = start;
+                            }
+                            Err(e) => error = error.or(Some(PgnError::Fen(e))),
+                        }
+                    }
+                    game.tags.push((name, value));
+                }
+                Token::VariationStart => depth += 1,
+                Token::VariationEnd => depth = (depth - 1).max(0),
+                Token::Symbol(_) if depth > 0 => {}
+                Token::Symbol(symbol) if RESULTS.contains(&symbol.as_str()) => {
+                    game.result = symbol;
+                    break;
+                }
+                Token::Symbol(symbol) => {
+                    // Move numbers may be glued to the move, as in `1.e4`
+                    let san = symbol.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                    if san.is_empty() || error.is_some() {
+                        continue;
+                    }
+
+                    match pos.parse_san(san) {
+                        Ok(m) => {
+                            pos = pos.make_move(m);
+                            game.moves.push(m);
+                        }
+                        Err(e) => error = Some(PgnError::San(e)),
+                    }
+                }
+            }
+        }
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(Some(game)),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_game().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PgnReader;
+
+    const PGN: &str = r#"[Event "Test \"quoted\""]
+[Site "?"]
+[Date "2023.09.01"]
+[Round "1"]
+[White "bernt"]
+[Black "bernt"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {main line} (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 $1 3. Bb5 a6
+; rest of the line is a comment
+4.Ba4 Nf6 5. O-O Be7 1-0
+
+[Event "?"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40"]
+
+40... Kd7 41. e4 *
+"#;
+
+    #[test]
+    fn read_write() {
+        let games: Vec<_> = PgnReader::new(PGN.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("Test \"quoted\""));
+        assert_eq!(games[0].moves.len(), 10);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].moves.len(), 2);
+        assert_eq!(games[1].result, "*");
+
+        for game in &games {
+            let written = game.to_string();
+            let reread = PgnReader::new(written.as_bytes()).next().unwrap().unwrap();
+
+            assert_eq!(reread.moves, game.moves);
+            assert_eq!(reread.start, game.start);
+            assert_eq!(reread.result, game.result);
+        }
+
+        assert!(games[1].to_string().contains("40... Kd7 41. e4 *"));
+    }
+
+    #[test]
+    fn missing_result() {
+        let pgn = "1. e4 e5 [Event\n\"next\"] [Site \"?\"] 1. d4 *";
+        let games: Vec<_> = PgnReader::new(pgn.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].moves.len(), 2);
+        assert_eq!(games[1].tag("Event"), Some("next"));
+        assert_eq!(games[1].tag("Site"), Some("?"));
+        assert_eq!(games[1].moves.len(), 1);
+    }
+}
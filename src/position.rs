@@ -7,6 +7,7 @@ pub struct Position {
     pub castling: [[u8; 2]; 2],
     pub en_passant: u8,
     pub halfmove: u8,
+    pub fullmove: u16,
     pub age: u16,
     pub side: PieceColor,
 }
@@ -42,6 +43,25 @@ pub struct Move {
     pub piece: PieceType,
 }
 
+impl Piece {
+    pub fn to_char(self) -> char {
+        let c = match self.ty {
+            PieceType::Pawn => 'p',
+            PieceType::Knight => 'n',
+            PieceType::Bishop => 'b',
+            PieceType::Rook => 'r',
+            PieceType::Queen => 'q',
+            PieceType::King => 'k',
+            PieceType::None => '.',
+        };
+
+        match self.color {
+            PieceColor::White => c.to_ascii_uppercase(),
+            PieceColor::Black => c,
+        }
+    }
+}
+
 impl Position {
     pub fn startpos() -> Self {
         Position {
@@ -57,6 +77,7 @@ impl Position {
             castling: [[0, 7], [56, 63]],
             en_passant: 64,
             halfmove: 0,
+            fullmove: 1,
             age: 1,
             side: PieceColor::White,
         }
@@ -69,6 +90,7 @@ impl Position {
             castling: [[64; 2]; 2],
             en_passant: 64,
             halfmove: 0,
+            fullmove: 1,
             age: 1,
             side: PieceColor::White,
         }
@@ -79,6 +101,10 @@ impl Position {
 
         let mut pos = self.clone();
 
+        if pos.side == PieceColor::Black {
+            pos.fullmove += 1;
+        }
+
         if m == Move::NULL {
             pos.en_passant = 64;
             pos.side = !pos.side;
@@ -114,20 +140,23 @@ impl Position {
 
         if piece == PieceType::King {
             pos.castling[side] = [64, 64];
-        } else if m.from == pos.castling[side][0] as u8 {
+        } else if m.from == pos.castling[side][0] {
             pos.castling[side][0] = 64;
-        } else if m.from == pos.castling[side][1] as u8 {
+        } else if m.from == pos.castling[side][1] {
             pos.castling[side][1] = 64;
         }
 
+        let mut dest_bit = to_bit;
+
         match m.flags {
-            MoveFlag::CASTLE_LEFT => {
-                pos.pieces[Rook] ^= to_bit << 1 | to_bit >> 2;
-                pos.colors[side] ^= to_bit << 1 | to_bit >> 2;
-            }
-            MoveFlag::CASTLE_RIGHT => {
-                pos.pieces[Rook] ^= to_bit << 1 | to_bit >> 1;
-                pos.colors[side] ^= to_bit << 1 | to_bit >> 1;
+            MoveFlag::CASTLE_LEFT | MoveFlag::CASTLE_RIGHT => {
+                // Castling is encoded as the king taking its own rook
+                let (king_to, rook_to) = m.castling_destinations();
+                let rook_bits = to_bit ^ 1 << rook_to;
+
+                pos.pieces[Rook] ^= rook_bits;
+                pos.colors[side] ^= rook_bits;
+                dest_bit = 1 << king_to;
             }
             MoveFlag::EP => {
                 let sq = (en_passant as i8
@@ -154,9 +183,9 @@ impl Position {
                     pos.colors[!side] ^= to_bit;
 
                     if target == Rook {
-                        if m.to == pos.castling[!side][0] as u8 {
+                        if m.to == pos.castling[!side][0] {
                             pos.castling[!side][0] = 64;
-                        } else if m.to == pos.castling[!side][1] as u8 {
+                        } else if m.to == pos.castling[!side][1] {
                             pos.castling[!side][1] = 64;
                         }
                     }
@@ -168,8 +197,8 @@ impl Position {
             }
         }
 
-        pos.pieces[piece] ^= to_bit;
-        pos.colors[side] ^= from_bit ^ to_bit;
+        pos.pieces[piece] ^= dest_bit;
+        pos.colors[side] ^= from_bit ^ dest_bit;
 
         pos.side = !pos.side;
 
@@ -205,65 +234,263 @@ impl Position {
         piece
     }
 
-    pub fn from_fen(s: &str) -> Self {
+    pub fn from_fen(s: &str) -> Result<Self, FenError> {
         let mut pos = Position::empty();
 
-        let mut i = 56;
-        let parts: Vec<_> = s.split(' ').collect();
-
-        for c in parts[0].chars() {
-            if c >= '1' && c <= '8' {
-                i += c as u8 - b'0';
-            } else if c == '/' {
-                i -= 16;
-            } else {
-                let color = c.is_lowercase();
-                let ty = match c.to_ascii_lowercase() {
-                    'p' => PieceType::Pawn,
-                    'n' => PieceType::Knight,
-                    'b' => PieceType::Bishop,
-                    'r' => PieceType::Rook,
-                    'q' => PieceType::Queen,
-                    'k' => PieceType::King,
-                    x => panic!("{x}"),
+        let mut parts = s.split_whitespace();
+        let board = parts.next().ok_or(FenError::MissingField("board"))?;
+        let side = parts.next().ok_or(FenError::MissingField("side to move"))?;
+        let castling = parts.next().ok_or(FenError::MissingField("castling"))?;
+        let en_passant = parts.next().ok_or(FenError::MissingField("en passant"))?;
+
+        let ranks: Vec<_> = board.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
+
+        for (rank, row) in ranks.iter().enumerate() {
+            let i = 56 - 8 * rank as u8;
+            let mut file = 0;
+
+            for c in row.chars() {
+                if ('1'..='8').contains(&c) {
+                    file += c as u8 - b'0';
+                } else {
+                    let color = c.is_lowercase();
+                    let ty = match c.to_ascii_lowercase() {
+                        'p' => PieceType::Pawn,
+                        'n' => PieceType::Knight,
+                        'b' => PieceType::Bishop,
+                        'r' => PieceType::Rook,
+                        'q' => PieceType::Queen,
+                        'k' => PieceType::King,
+                        _ => return Err(FenError::InvalidPiece(c)),
+                    };
+
+                    if file < 8 {
+                        pos.pieces[ty as usize] |= 1 << (i + file);
+                        pos.colors[color as usize] |= 1 << (i + file);
+                    }
+                    file += 1;
+                }
+
+                if file > 8 {
+                    return Err(FenError::RankLength(8 - rank as u8));
+                }
+            }
+
+            if file != 8 {
+                return Err(FenError::RankLength(8 - rank as u8));
+            }
+        }
+
+        for side in [PieceColor::White, PieceColor::Black] {
+            if (pos.pieces[PieceType::King] & pos.colors[side]).count_ones() != 1 {
+                return Err(FenError::MissingKing(side));
+            }
+        }
+
+        pos.side = match side {
+            "w" => PieceColor::White,
+            "b" => PieceColor::Black,
+            _ => return Err(FenError::SideToMove(side.to_string())),
+        };
+
+        if castling != "-" {
+            for c in castling.chars() {
+                let side = if c.is_ascii_lowercase() {
+                    PieceColor::Black
+                } else {
+                    PieceColor::White
+                };
+                let back_rank = 56 * side as u8;
+                let rooks = (pos.pieces[PieceType::Rook] & pos.colors[side]) >> back_rank & 0xff;
+                let king = ((pos.pieces[PieceType::King] & pos.colors[side]) >> back_rank & 0xff)
+                    .trailing_zeros() as u8;
+
+                // X-FEN letters name the outermost rook, Shredder-FEN letters name the rook's file
+                let file = match c.to_ascii_lowercase() {
+                    'k' => 63u32
+                        .checked_sub(rooks.leading_zeros())
+                        .filter(|&f| f as u8 > king),
+                    'q' => Some(rooks.trailing_zeros()).filter(|&f| (f as u8) < king),
+                    f @ 'a'..='h' => Some(f as u32 - 'a' as u32),
+                    _ => None,
                 };
 
-                pos.pieces[ty as usize] |= 1 << i;
-                pos.colors[color as usize] |= 1 << i;
-                i += 1;
+                match file.filter(|&f| f < 8 && king < 8 && rooks & 1 << f != 0) {
+                    Some(file) => {
+                        pos.castling[side][(file as u8 > king) as usize] = back_rank + file as u8
+                    }
+                    None => return Err(FenError::Castling(castling.to_string())),
+                }
             }
         }
 
-        if parts[1] == "b" {
-            pos.side = PieceColor::Black;
+        if en_passant != "-" {
+            let sq = parse_sq(en_passant)
+                .filter(|sq| match pos.side {
+                    PieceColor::White => sq / 8 == 5,
+                    PieceColor::Black => sq / 8 == 2,
+                })
+                .ok_or_else(|| FenError::EnPassant(en_passant.to_string()))?;
+            pos.en_passant = sq;
+        }
+
+        // EPD positions come without the move counters
+        if let Some(halfmove) = parts.next() {
+            pos.halfmove = halfmove
+                .parse()
+                .map_err(|_| FenError::Counter(halfmove.to_string()))?;
+        }
+        if let Some(fullmove) = parts.next() {
+            pos.fullmove = fullmove
+                .parse()
+                .ok()
+                .filter(|&x| x > 0)
+                .ok_or_else(|| FenError::Counter(fullmove.to_string()))?;
         }
 
-        for c in parts[2].chars() {
-            match c {
-                'Q' => pos.castling[0][0] = 0,
-                'K' => pos.castling[0][1] = 7,
-                'q' => pos.castling[1][0] = 56,
-                'k' => pos.castling[1][1] = 63,
-                // TODO: Shredder FEN
-                _ => {}
+        // The king of the side not to move could be captured, which nothing downstream handles
+        if pos.in_check(!pos.side) {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        Ok(pos)
+    }
+}
+
+impl Position {
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+
+            for file in 0..8 {
+                let piece = self.piece_at(rank * 8 + file);
+
+                if piece.ty == PieceType::None {
+                    empty += 1;
+                    continue;
+                }
+
+                if empty > 0 {
+                    fen.push((b'0' + empty) as char);
+                    empty = 0;
+                }
+                fen.push(piece.to_char());
+            }
+
+            if empty > 0 {
+                fen.push((b'0' + empty) as char);
+            }
+            if rank > 0 {
+                fen.push('/');
             }
         }
 
-        if parts[3] != "-" {
-            pos.en_passant = uci_sq(parts[3]);
+        fen.push_str(match self.side {
+            PieceColor::White => " w",
+            PieceColor::Black => " b",
+        });
+
+        fen.push(' ');
+        fen.push_str(&self.castling_string());
+
+        fen.push(' ');
+        if self.en_passant == 64 {
+            fen.push('-');
+        } else {
+            fen.push_str(&format_sq(self.en_passant));
         }
 
-        pos.halfmove = parts.get(4).and_then(|x| x.parse().ok()).unwrap_or(0);
-        pos.halfmove = parts.get(5).and_then(|x| x.parse().ok()).unwrap_or(1);
+        fen.push_str(&format!(" {} {}", self.halfmove, self.fullmove));
 
-        return pos;
+        fen
+    }
+
+    /// Castling rights in X-FEN notation, falling back to Shredder-FEN file letters for rooks
+    /// that are not the outermost one on their side of the king
+    pub fn castling_string(&self) -> String {
+        let mut s = String::new();
+
+        for side in [PieceColor::White, PieceColor::Black] {
+            let back_rank =
+                (self.pieces[PieceType::Rook] & self.colors[side]) >> (56 * side as u8) & 0xff;
+
+            for (i, letter) in [(1, 'k'), (0, 'q')] {
+                let rook = self.castling[side][i];
+                if rook == 64 {
+                    continue;
+                }
+
+                let outermost = if i == 1 {
+                    63 - back_rank.leading_zeros()
+                } else {
+                    back_rank.trailing_zeros()
+                };
+                let c = if outermost == (rook % 8) as u32 {
+                    letter
+                } else {
+                    (b'a' + rook % 8) as char
+                };
+
+                s.push(match side {
+                    PieceColor::White => c.to_ascii_uppercase(),
+                    PieceColor::Black => c,
+                });
+            }
+        }
+
+        if s.is_empty() {
+            s.push('-');
+        }
+
+        s
+    }
+}
+
+pub fn parse_sq(sq: &str) -> Option<u8> {
+    match sq.as_bytes() {
+        &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some(file - b'a' + 8 * (rank - b'1')),
+        _ => None,
     }
 }
 
-fn uci_sq(sq: &str) -> u8 {
-    sq.as_bytes()[0] - b'a' + 8 * (sq.as_bytes()[1] as u8 - b'1')
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    RankCount(usize),
+    RankLength(u8),
+    InvalidPiece(char),
+    MissingKing(PieceColor),
+    SideToMove(String),
+    Castling(String),
+    EnPassant(String),
+    Counter(String),
+    OpponentInCheck,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingField(field) => write!(f, "missing {field} field"),
+            Self::RankCount(n) => write!(f, "expected 8 ranks, found {n}"),
+            Self::RankLength(rank) => write!(f, "rank {rank} does not have 8 squares"),
+            Self::InvalidPiece(c) => write!(f, "invalid piece '{c}'"),
+            Self::MissingKing(side) => write!(f, "{side:?} does not have exactly one king"),
+            Self::SideToMove(side) => write!(f, "invalid side to move \"{side}\""),
+            Self::Castling(castling) => write!(f, "invalid castling field \"{castling}\""),
+            Self::EnPassant(sq) => write!(f, "invalid en passant square \"{sq}\""),
+            Self::Counter(counter) => write!(f, "invalid move counter \"{counter}\""),
+            Self::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
 }
 
+impl std::error::Error for FenError {}
+
 pub struct MoveFlag;
 
 impl MoveFlag {
@@ -294,10 +521,31 @@ impl Move {
         }
     }
 
+    /// Destination squares of the king and the rook for a castling move
+    pub fn castling_destinations(&self) -> (u8, u8) {
+        let rank = self.from & 56;
+
+        if self.flags == MoveFlag::CASTLE_LEFT {
+            (rank + 2, rank + 3)
+        } else {
+            (rank + 6, rank + 5)
+        }
+    }
+
+    pub fn castling(&self) -> bool {
+        self.flags == MoveFlag::CASTLE_LEFT || self.flags == MoveFlag::CASTLE_RIGHT
+    }
+
     pub fn capture(&self) -> bool {
         self.flags & MoveFlag::CAP != 0
     }
 
+    /// Formats the move in UCI notation, with castling written as the king taking its rook if
+    /// `chess960` is set. [`Move`]'s own `Display` uses standard notation.
+    pub fn display(self, chess960: bool) -> MoveDisplay {
+        MoveDisplay { m: self, chess960 }
+    }
+
     pub fn promotion(&self) -> PieceType {
         use PieceType::*;
 
@@ -322,20 +570,40 @@ impl Default for Move {
 }
 
 impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(false).fmt(f)
+    }
+}
+
+pub struct MoveDisplay {
+    m: Move,
+    chess960: bool,
+}
+
+impl fmt::Display for MoveDisplay {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         const PROMOS: [&str; 7] = ["", "n", "b", "r", "q", "", ""];
 
+        let m = self.m;
+
+        // Outside of Chess960, castling is written as the king's two square move
+        let to = if m.castling() && !self.chess960 {
+            m.castling_destinations().0
+        } else {
+            m.to
+        };
+
         write!(
             f,
             "{}{}{}",
-            format_sq(self.from),
-            format_sq(self.to),
-            PROMOS[self.promotion()]
+            format_sq(m.from),
+            format_sq(to),
+            PROMOS[m.promotion()]
         )
     }
 }
 
-fn format_sq(sq: u8) -> String {
+pub fn format_sq(sq: u8) -> String {
     const FILES: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
     const RANKS: [char; 8] = ['1', '2', '3', '4', '5', '6', '7', '8'];
 
@@ -350,6 +618,28 @@ fn format_sq(sq: u8) -> String {
     s
 }
 
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const SEPARATOR: &str = " +---+---+---+---+---+---+---+---+";
+
+        writeln!(f, "{SEPARATOR}")?;
+        for rank in (0..8).rev() {
+            for file in 0..8 {
+                let piece = self.piece_at(rank * 8 + file);
+                let c = if piece.ty == PieceType::None {
+                    ' '
+                } else {
+                    piece.to_char()
+                };
+                write!(f, " | {c}")?;
+            }
+            writeln!(f, " | {}", rank + 1)?;
+            writeln!(f, "{SEPARATOR}")?;
+        }
+        write!(f, "   a   b   c   d   e   f   g   h")
+    }
+}
+
 impl fmt::Debug for Position {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use PieceType::*;
@@ -366,8 +656,13 @@ impl fmt::Debug for Position {
             writeln!(f, "{ty}: {x:#018x}")?;
         }
 
-        // TODO: Print more
-        Ok(())
+        writeln!(f, "Side: {:?}", self.side)?;
+        writeln!(f, "Castling: {:?}", self.castling)?;
+        writeln!(f, "En passant: {}", self.en_passant)?;
+        writeln!(f, "Halfmove: {}", self.halfmove)?;
+        writeln!(f, "Fullmove: {}", self.fullmove)?;
+        writeln!(f, "Age: {}", self.age)?;
+        write!(f, "Fen: {}", self.to_fen())
     }
 }
 
@@ -414,6 +709,75 @@ impl ops::Not for PieceColor {
 fn startpos_fen() {
     assert_eq!(
         Position::startpos(),
-        Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+        Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
     );
 }
+
+#[test]
+fn fen_roundtrip() {
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQk - 2 9",
+        "1r2k1rr/8/8/8/8/8/8/R1K3R1 b Qg - 0 34",
+    ] {
+        assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+    }
+}
+
+#[test]
+fn fen_errors() {
+    for (fen, err) in [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            FenError::RankCount(7),
+        ),
+        (
+            "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            FenError::RankLength(7),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/44p/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            FenError::RankLength(4),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+            FenError::InvalidPiece('X'),
+        ),
+        (
+            "4k3/8/8/8/8/8/8/8 w - - 0 1",
+            FenError::MissingKing(PieceColor::White),
+        ),
+        (
+            "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+            FenError::Castling("K".to_string()),
+        ),
+        (
+            "4k3/8/8/8/8/8/8/4K3 w - e4 0 1",
+            FenError::EnPassant("e4".to_string()),
+        ),
+        (
+            "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+            FenError::Counter("x".to_string()),
+        ),
+        (
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+            FenError::Counter("0".to_string()),
+        ),
+        ("4k3/8/8/8/8/8/8/4K3 w", FenError::MissingField("castling")),
+        ("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1", FenError::OpponentInCheck),
+    ] {
+        assert_eq!(Position::from_fen(fen), Err(err));
+    }
+}
+
+#[test]
+fn fullmove_counter() {
+    let e4 = Move::new(12, 28, MoveFlag::DOUBLE_PAWN, PieceType::Pawn);
+    let e5 = Move::new(52, 36, MoveFlag::DOUBLE_PAWN, PieceType::Pawn);
+
+    let pos = Position::startpos().make_move(e4);
+    assert_eq!(pos.fullmove, 1);
+    assert_eq!(pos.make_move(e5).fullmove, 2);
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+
+use crate::{
+    movegen::legal_movegen,
+    position::{format_sq, parse_sq, Move, MoveFlag, PieceType, Position},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    Invalid(String),
+    Illegal(String),
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(san) => write!(f, "invalid move \"{san}\""),
+            Self::Illegal(san) => write!(f, "illegal move \"{san}\""),
+            Self::Ambiguous(san) => write!(f, "ambiguous move \"{san}\""),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+const PIECE_LETTERS: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+
+impl Position {
+    /// Formats the legal move `m` in Standard Algebraic Notation, including the check or mate
+    /// suffix
+    pub fn move_to_san(&self, m: Move) -> String {
+        let mut san = String::new();
+
+        if m.flags == MoveFlag::CASTLE_LEFT {
+            san.push_str("O-O-O");
+        } else if m.flags == MoveFlag::CASTLE_RIGHT {
+            san.push_str("O-O");
+        } else if m.piece == PieceType::Pawn {
+            if m.capture() {
+                san.push((b'a' + m.from % 8) as char);
+                san.push('x');
+            }
+            san.push_str(&format_sq(m.to));
+
+            if m.promotion() != PieceType::None {
+                san.push('=');
+                san.push(PIECE_LETTERS[m.promotion()]);
+            }
+        } else {
+            san.push(PIECE_LETTERS[m.piece]);
+
+            let others: Vec<_> = legal_movegen(self)
+                .into_iter()
+                .filter(|n| n.piece == m.piece && n.to == m.to && n.from != m.from && !n.castling())
+                .map(|n| n.from)
+                .collect();
+
+            if !others.is_empty() {
+                if others.iter().all(|sq| sq % 8 != m.from % 8) {
+                    san.push((b'a' + m.from % 8) as char);
+                } else if others.iter().all(|sq| sq / 8 != m.from / 8) {
+                    san.push((b'1' + m.from / 8) as char);
+                } else {
+                    san.push_str(&format_sq(m.from));
+                }
+            }
+
+            if m.capture() {
+                san.push('x');
+            }
+            san.push_str(&format_sq(m.to));
+        }
+
+        let pos = self.make_move(m);
+        if pos.in_check(pos.side) {
+            san.push(if legal_movegen(&pos).is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+
+        san
+    }
+
+    /// Finds the legal move written as `san`. Check and annotation suffixes are ignored, and
+    /// missing capture signs, superfluous disambiguation and promotions without `=` are accepted.
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let invalid = || SanError::Invalid(san.to_string());
+
+        let s = san.trim_end_matches(['+', '#', '!', '?']);
+        let moves = legal_movegen(self);
+
+        let castle = match s {
+            "O-O" | "0-0" => Some(MoveFlag::CASTLE_RIGHT),
+            "O-O-O" | "0-0-0" => Some(MoveFlag::CASTLE_LEFT),
+            _ => None,
+        };
+        if let Some(flag) = castle {
+            return moves
+                .into_iter()
+                .find(|m| m.flags == flag)
+                .copied()
+                .ok_or_else(|| SanError::Illegal(san.to_string()));
+        }
+
+        let mut chars: Vec<char> = s.chars().collect();
+
+        let piece = match chars.first() {
+            Some(&c) if "NBRQK".contains(c) => {
+                chars.remove(0);
+                PIECE_LETTERS.iter().position(|&x| x == c).unwrap()
+            }
+            Some(_) => PieceType::Pawn as usize,
+            None => return Err(invalid()),
+        };
+
+        let mut promotion = PieceType::None;
+        if let Some(&c) = chars.last().filter(|c| "NBRQ".contains(**c)) {
+            promotion = [
+                PieceType::Knight,
+                PieceType::Bishop,
+                PieceType::Rook,
+                PieceType::Queen,
+            ]["NBRQ".find(c).unwrap()];
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        if chars.len() < 2 {
+            return Err(invalid());
+        }
+        let to: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = parse_sq(&to).ok_or_else(invalid)?;
+
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+
+        let (mut file, mut rank) = (None, None);
+        for c in chars {
+            match c {
+                'a'..='h' if file.is_none() => file = Some(c as u8 - b'a'),
+                '1'..='8' if rank.is_none() => rank = Some(c as u8 - b'1'),
+                _ => return Err(invalid()),
+            }
+        }
+
+        let mut candidates = moves.into_iter().filter(|m| {
+            m.piece as usize == piece
+                && m.to == to
+                && !m.castling()
+                && m.promotion() == promotion
+                && file.map_or(true, |f| m.from % 8 == f)
+                && rank.map_or(true, |r| m.from / 8 == r)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(m), None) => Ok(*m),
+            (Some(_), Some(_)) => Err(SanError::Ambiguous(san.to_string())),
+            (None, _) => Err(SanError::Illegal(san.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SanError;
+    use crate::{movegen::legal_movegen, position::Position};
+
+    #[test]
+    fn san_roundtrip() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let pos = Position::from_fen(fen).unwrap();
+
+            for m in &legal_movegen(&pos) {
+                assert_eq!(pos.parse_san(&pos.move_to_san(*m)), Ok(*m));
+            }
+        }
+    }
+
+    #[test]
+    fn san_formatting() {
+        let pos = Position::from_fen("6k1/5ppp/8/8/8/8/1R6/R3K2R w KQ - 0 1").unwrap();
+
+        for (san, expected) in [
+            ("O-O", "O-O"),
+            ("0-0-0", "O-O-O"),
+            ("Rb7", "Rb7"),
+            ("R2b1", "Rbb1"),
+            ("Rb8", "Rb8#"),
+            ("Ra1a3", "Ra3"),
+            ("Rbxf2", "Rf2"),
+        ] {
+            assert_eq!(pos.move_to_san(pos.parse_san(san).unwrap()), expected);
+        }
+
+        assert_eq!(
+            pos.parse_san("Rb1"),
+            Err(SanError::Ambiguous("Rb1".to_string()))
+        );
+        assert_eq!(
+            pos.parse_san("Ke3"),
+            Err(SanError::Illegal("Ke3".to_string()))
+        );
+        assert_eq!(
+            pos.parse_san("Zz9"),
+            Err(SanError::Invalid("Zz9".to_string()))
+        );
+    }
+}
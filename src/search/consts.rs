@@ -20,6 +20,9 @@ pub const FP_DEPTH: u8 = 4;
 pub const FP_BASE: i32 = 231;
 pub const FP_MUL: i32 = 504;
 
+pub const SEE_QUIET_DEPTH: u8 = 6;
+pub const SEE_QUIET_MARGIN: i32 = 64;
+
 pub const HIST_MUL: i32 = 287;
 pub const HIST_ADD: i32 = -428;
 pub const CONTHIST_MUL: i32 = 414;
@@ -3,6 +3,11 @@ use crate::{
     position::{PieceColor, PieceType, Position},
 };
 
+/// Static evaluation in centipawns from the point of view of the side to move
+pub fn evaluate(pos: &Position) -> i32 {
+    eval(pos).0
+}
+
 pub fn eval(pos: &Position) -> (i32, i32, i32, i32) {
     let mut mg = 0;
     let mut eg = 0;
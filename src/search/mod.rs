@@ -5,10 +5,19 @@ mod qsearch;
 mod timeman;
 pub mod tt;
 
-use std::time::{Duration, Instant};
+use std::{
+    alloc::{self, Layout},
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    movegen::movegen,
+    movegen::{legal_movegen, mobility},
     position::{Move, MoveFlag, PieceColor, PieceType, Position},
     search::eval::{flip, EG_PSTS, MG_PSTS, PHASE},
     zobrist, SearchOptions,
@@ -22,19 +31,49 @@ use self::{
 };
 
 struct SearchContext<'a> {
+    id: usize,
+    start: Instant,
     timeman: TimeManager,
     nodes: u64,
+    flushed_nodes: u64,
+    global_nodes: &'a AtomicU64,
+    seldepth: u8,
+    reporter: Arc<dyn SearchReporter>,
     repetitions: Vec<u64>,
-    tt: &'a mut TT,
+    tt: &'a TT,
     killers: [[Move; 2]; 256],
+    counters: [[[Move; 64]; 6]; 2],
     history: [[[i32; 64]; 6]; 2],
-    continuations: [[[[[[i32; 64]; 6]; 64]; 6]; 2]; 2],
+    continuations: Box<ContinuationHistory>,
     move_stack: [Move; 256],
+    pv: [[Move; 256]; 256],
+    pv_len: [usize; 256],
+    excluded: Vec<Move>,
+    searchmoves: Vec<Move>,
     tt_age: u16,
 }
 
+type ContinuationHistory = [[[[[[i32; 64]; 6]; 64]; 6]; 2]; 2];
+
+/// Allocates the zeroed table directly on the heap, `Box::new` would build its 2.4 MB on the stack
+/// first
+fn new_continuation_history() -> Box<ContinuationHistory> {
+    let layout = Layout::new::<ContinuationHistory>();
+
+    // SAFETY: the layout is not zero-sized, and all zero bytes are a valid array of integers
+    unsafe {
+        let ptr = alloc::alloc_zeroed(layout) as *mut ContinuationHistory;
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+
+        Box::from_raw(ptr)
+    }
+}
+
 struct SearchPosition {
     pos: Position,
+    hash: u64,
     eval: i32,
     mg_eval: i32,
     eg_eval: i32,
@@ -42,9 +81,96 @@ struct SearchPosition {
     mobility: [i32; 2],
 }
 
+/// Receives the progress of a search from the main search thread. Every event does nothing by
+/// default, so implementors only override what they need.
+pub trait SearchReporter: Send + Sync {
+    /// An iteration of a principal variation completed
+    fn iteration(&self, _info: &SearchInfo) {}
+
+    /// The first principal variation got a new best move during the iteration at `depth`. The
+    /// score may still be a bound if the aspiration window fails.
+    fn best_move(&self, _depth: u8, _m: Move, _score: i32) {}
+
+    /// A root move starts being searched, only reported once the search ran for a few seconds
+    fn currmove(&self, _depth: u8, _m: Move, _number: u16) {}
+
+    /// The search finished
+    fn result(&self, _res: &SearchResult) {}
+}
+
+/// Reports nothing
+pub struct NullReporter;
+
+impl SearchReporter for NullReporter {}
+
+/// Progress of the search, reported whenever an iteration of a principal variation completes
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: u8,
+    pub seldepth: u8,
+    /// 1-based index of the principal variation
+    pub multipv: usize,
+    pub score: i32,
+    pub nodes: u64,
+    pub nps: u64,
+    /// Permill of the transposition table in use
+    pub hashfull: usize,
+    pub time: Duration,
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    /// Formats the UCI `info` line, with castling in the principal variation written as the king
+    /// taking its rook if `chess960` is set
+    pub fn display(&self, chess960: bool) -> SearchInfoDisplay<'_> {
+        SearchInfoDisplay {
+            info: self,
+            chess960,
+        }
+    }
+}
+
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(false).fmt(f)
+    }
+}
+
+pub struct SearchInfoDisplay<'a> {
+    info: &'a SearchInfo,
+    chess960: bool,
+}
+
+impl fmt::Display for SearchInfoDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = self.info;
+        let pv = info
+            .pv
+            .iter()
+            .map(|m| m.display(self.chess960).to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        write!(
+            f,
+            "info depth {} seldepth {} multipv {} score {} nodes {} nps {} hashfull {} time {} pv {pv}",
+            info.depth,
+            info.seldepth,
+            info.multipv,
+            format_score(info.score),
+            info.nodes,
+            info.nps,
+            info.hashfull,
+            info.time.as_millis()
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct SearchResult {
     pub best: Move,
     pub score: i32,
+    pub pv: Vec<Move>,
     pub nodes: u64,
     pub elapsed: Duration,
 }
@@ -53,28 +179,23 @@ pub fn search(
     pos: &Position,
     options: SearchOptions,
     repetitions: Vec<u64>,
-    tt: &mut TT,
+    tt: &TT,
 ) -> SearchResult {
     let instant = Instant::now();
+    let global_nodes = AtomicU64::new(0);
 
-    let mut context = SearchContext {
-        timeman: TimeManager::new(&options, pos.side),
-        nodes: 0,
-        repetitions,
-        tt,
-        killers: [[Move::NULL; 2]; 256],
-        history: [[[0; 64]; 6]; 2],
-        continuations: [[[[[[0; 64]; 6]; 64]; 6]; 2]; 2],
-        move_stack: [Move::NULL; 256],
-        tt_age: pos.age,
-    };
+    let multipv = legal_movegen(pos)
+        .into_iter()
+        .filter(|m| options.searchmoves.is_empty() || options.searchmoves.contains(m))
+        .count()
+        .min(options.multipv)
+        .max(1);
 
-    let mut best = (Move::NULL, -INF);
-
-    let (eval, mg_eval, eg_eval, phase) = eval(&pos);
+    let (eval, mg_eval, eg_eval, phase) = eval(pos);
 
     let pos = SearchPosition {
         pos: pos.clone(),
+        hash: pos.hash(),
         eval,
         mg_eval,
         eg_eval,
@@ -82,63 +203,120 @@ pub fn search(
         mobility: [0; 2],
     };
 
-    for depth in 1..=options.depth {
-        if depth > 1 && (context.timeman.soft_stop() || context.timeman.hard_stop()) {
-            break;
+    // Helpers only stop once the main thread is done
+    let helper_options = SearchOptions {
+        infinite: true,
+        nodes: None,
+        reporter: Arc::new(NullReporter),
+        stop: Arc::new(AtomicBool::new(false)),
+        ponder: Arc::new(AtomicBool::new(false)),
+        ..options.clone()
+    };
+
+    let lines = thread::scope(|s| {
+        for id in 1..options.threads.max(1) {
+            let repetitions = repetitions.clone();
+            let (pos, helper_options, global_nodes) = (&pos, &helper_options, &global_nodes);
+
+            thread::Builder::new()
+                .stack_size(8_000_000)
+                .spawn_scoped(s, move || {
+                    let mut context = Box::new(SearchContext::new(
+                        id,
+                        helper_options,
+                        &pos.pos,
+                        repetitions,
+                        tt,
+                        global_nodes,
+                    ));
+                    context.iterative_deepening(pos, helper_options, multipv);
+                    context.flush_nodes();
+                })
+                .unwrap();
         }
 
-        let mut window_size = ASP_WINDOW;
-        let mut alpha = -INF;
-        let mut beta = INF;
+        // The context is too large for the stack of the caller
+        let mut context = Box::new(SearchContext::new(
+            0,
+            &options,
+            &pos.pos,
+            repetitions,
+            tt,
+            &global_nodes,
+        ));
+        let lines = context.iterative_deepening(&pos, &options, multipv);
+        context.flush_nodes();
 
-        if depth >= ASP_DEPTH {
-            alpha = best.1 - window_size;
-            beta = best.1 + window_size;
-        }
+        helper_options.stop.store(true, Ordering::Relaxed);
 
-        loop {
-            let b = if let Some((m, score)) = context.negamax(&pos, alpha, beta, 0, depth, false) {
-                (m, score)
-            } else {
-                break;
-            };
+        lines
+    });
 
-            if b.1 <= alpha {
-                beta = (alpha + beta) / 2;
-                alpha -= window_size;
-            } else if b.1 >= beta {
-                beta += window_size;
-            } else {
-                best = b;
-                break;
-            }
+    let (best, score, pv) = lines
+        .into_iter()
+        .next()
+        .unwrap_or((Move::NULL, -INF, vec![]));
 
-            window_size += (window_size as f32 * ASP_INC_FACTOR) as i32;
-        }
+    let res = SearchResult {
+        best,
+        score,
+        pv,
+        nodes: global_nodes.load(Ordering::Relaxed),
+        elapsed: instant.elapsed(),
+    };
+    options.reporter.result(&res);
 
-        let elapsed = instant.elapsed();
-        let nodes = context.nodes;
-        let nps = (nodes as f32 / elapsed.as_secs_f32()) as u64;
-        let elapsed = elapsed.as_millis();
-        let (m, score) = best;
+    res
+}
 
-        if options.info {
-            println!(
-                "info depth {depth} score cp {score} nodes {nodes} nps {nps} time {elapsed} pv {m}"
-            );
-        }
+const INF: i32 = 1000000;
+pub const CHECKMATE: i32 = 100000;
+
+const CURRMOVE_DELAY: Duration = Duration::from_secs(3);
+const NODES_FLUSH: u64 = 1024;
+
+// Depth skipping pattern for helper threads, so they do not all search the same depth
+const SKIP_SIZE: [usize; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [usize; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+/// Moves until mate for the side to move, negative if it is getting mated.
+pub fn mate_distance(score: i32) -> Option<i32> {
+    if score >= CHECKMATE {
+        Some((CHECKMATE + 255 - score + 1) / 2)
+    } else if score <= -CHECKMATE {
+        Some(-(CHECKMATE + 255 + score) / 2)
+    } else {
+        None
     }
+}
 
-    SearchResult {
-        best: best.0,
-        score: best.1,
-        nodes: context.nodes,
-        elapsed: instant.elapsed(),
+/// Converts a mate score from root relative to node relative for storing in the TT.
+fn score_to_tt(score: i32, ply: u8) -> i32 {
+    if score >= CHECKMATE {
+        score + ply as i32
+    } else if score <= -CHECKMATE {
+        score - ply as i32
+    } else {
+        score
     }
 }
 
-const INF: i32 = 1000000;
-pub const CHECKMATE: i32 = 100000;
+fn score_from_tt(score: i32, ply: u8) -> i32 {
+    if score >= CHECKMATE {
+        score - ply as i32
+    } else if score <= -CHECKMATE {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+fn format_score(score: i32) -> String {
+    match mate_distance(score) {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {score}"),
+    }
+}
 
 pub fn is_draw(pos: &Position, reps: &[u64]) -> bool {
     if pos.halfmove >= 100 {
@@ -165,28 +343,168 @@ pub fn is_draw(pos: &Position, reps: &[u64]) -> bool {
     false
 }
 
-impl SearchContext<'_> {
+impl<'a> SearchContext<'a> {
+    fn new(
+        id: usize,
+        options: &SearchOptions,
+        pos: &Position,
+        repetitions: Vec<u64>,
+        tt: &'a TT,
+        global_nodes: &'a AtomicU64,
+    ) -> Self {
+        Self {
+            id,
+            start: Instant::now(),
+            timeman: TimeManager::new(options, pos.side),
+            nodes: 0,
+            flushed_nodes: 0,
+            global_nodes,
+            seldepth: 0,
+            reporter: options.reporter.clone(),
+            repetitions,
+            tt,
+            killers: [[Move::NULL; 2]; 256],
+            counters: [[[Move::NULL; 64]; 6]; 2],
+            history: [[[0; 64]; 6]; 2],
+            continuations: new_continuation_history(),
+            move_stack: [Move::NULL; 256],
+            pv: [[Move::NULL; 256]; 256],
+            pv_len: [0; 256],
+            excluded: vec![],
+            searchmoves: options.searchmoves.clone(),
+            tt_age: pos.age,
+        }
+    }
+
+    fn iterative_deepening(
+        &mut self,
+        pos: &SearchPosition,
+        options: &SearchOptions,
+        multipv: usize,
+    ) -> Vec<(Move, i32, Vec<Move>)> {
+        let mut lines: Vec<(Move, i32, Vec<Move>)> = vec![];
+
+        'iterative_deepening: for depth in 1..=options.depth {
+            if depth > 1
+                && (self.timeman.soft_stop()
+                    || self.timeman.hard_stop()
+                    || self.timeman.node_stop(self.total_nodes()))
+            {
+                break;
+            }
+
+            if self.id > 0 {
+                let i = (self.id - 1) % SKIP_SIZE.len();
+                if (depth as usize + SKIP_PHASE[i]) / SKIP_SIZE[i] % 2 != 0 {
+                    continue;
+                }
+            }
+
+            self.seldepth = 0;
+            self.excluded.clear();
+
+            for pv_idx in 0..multipv {
+                let mut window_size = ASP_WINDOW;
+                let mut alpha = -INF;
+                let mut beta = INF;
+
+                if depth >= ASP_DEPTH {
+                    if let Some(&(_, score, _)) = lines.get(pv_idx) {
+                        alpha = score - window_size;
+                        beta = score + window_size;
+                    }
+                }
+
+                let mut line = None;
+
+                while let Some(b) = self.negamax(pos, alpha, beta, 0, depth, false) {
+                    if b.1 <= alpha {
+                        beta = (alpha + beta) / 2;
+                        alpha -= window_size;
+                    } else if b.1 >= beta {
+                        beta += window_size;
+                    } else {
+                        line = Some((b.0, b.1, self.pv[0][..self.pv_len[0]].to_vec()));
+                        break;
+                    }
+
+                    window_size += (window_size as f32 * ASP_INC_FACTOR) as i32;
+                }
+
+                let Some(line) = line else {
+                    break 'iterative_deepening;
+                };
+
+                let time = self.start.elapsed();
+                let nodes = self.total_nodes();
+                self.reporter.iteration(&SearchInfo {
+                    depth,
+                    seldepth: self.seldepth,
+                    multipv: pv_idx + 1,
+                    score: line.1,
+                    nodes,
+                    nps: (nodes as f32 / time.as_secs_f32()) as u64,
+                    hashfull: self.tt.hashfull(self.tt_age),
+                    time,
+                    pv: line.2.clone(),
+                });
+
+                let best = line.0;
+                if pv_idx < lines.len() {
+                    lines[pv_idx] = line;
+                } else {
+                    lines.push(line);
+                }
+
+                if best == Move::NULL {
+                    break;
+                }
+                self.excluded.push(best);
+            }
+
+            if let Some(mate) = options.mate {
+                if mate_distance(lines[0].1).is_some_and(|d| d > 0 && d <= mate as i32) {
+                    break;
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Nodes searched by all threads, including this thread's unflushed nodes.
+    fn total_nodes(&self) -> u64 {
+        self.global_nodes.load(Ordering::Relaxed) + self.nodes - self.flushed_nodes
+    }
+
+    fn flush_nodes(&mut self) {
+        self.global_nodes
+            .fetch_add(self.nodes - self.flushed_nodes, Ordering::Relaxed);
+        self.flushed_nodes = self.nodes;
+    }
+
     fn is_draw(&self, pos: &Position) -> bool {
         is_draw(pos, &self.repetitions)
     }
 
-    fn update(&mut self, pos: &SearchPosition, m: Move, update_hash: bool) -> SearchPosition {
+    fn update(&mut self, pos: &SearchPosition, m: Move, push_repetition: bool) -> SearchPosition {
         use PieceType::*;
 
         let mut mg = pos.mg_eval;
         let mut eg = pos.eg_eval;
         let mut phase = pos.phase;
 
-        let mut hash = *self.repetitions.last().unwrap();
+        let mut hash = pos.hash;
 
         if m == Move::NULL {
-            if update_hash {
-                hash ^= zobrist::BLACK;
+            hash ^= zobrist::BLACK;
+            if push_repetition {
                 self.repetitions.push(hash);
             }
 
             return SearchPosition {
                 pos: pos.pos.make_move(m),
+                hash,
                 eval: -pos.eval,
                 mg_eval: -mg,
                 eg_eval: -eg,
@@ -203,51 +521,42 @@ impl SearchContext<'_> {
         mg -= MG_PSTS[piece][flip(m.from, side) as usize];
         eg -= EG_PSTS[piece][flip(m.from, side) as usize];
 
-        if update_hash {
-            hash ^= zobrist::PIECES[m.from as usize][side][piece];
+        hash ^= zobrist::PIECES[m.from as usize][side][piece];
 
-            if pos.pos.en_passant != 64 {
-                hash ^= zobrist::EN_PASSANT[pos.pos.en_passant as usize % 8];
-            }
+        if pos.pos.en_passant != 64 {
+            hash ^= zobrist::EN_PASSANT[pos.pos.en_passant as usize % 8];
+        }
 
-            if m.flags == MoveFlag::DOUBLE_PAWN {
-                hash ^= zobrist::EN_PASSANT[m.to as usize % 8];
-            }
+        if m.flags == MoveFlag::DOUBLE_PAWN {
+            hash ^= zobrist::EN_PASSANT[m.to as usize % 8];
+        }
 
-            if piece == PieceType::King {
-                if pos.pos.castling[side][0] != 64 {
-                    hash ^= zobrist::CASTLING[side][0];
-                }
-                if pos.pos.castling[side][1] != 64 {
-                    hash ^= zobrist::CASTLING[side][1];
-                }
-            } else if m.from == pos.pos.castling[side][0] {
+        if piece == PieceType::King {
+            if pos.pos.castling[side][0] != 64 {
                 hash ^= zobrist::CASTLING[side][0];
-            } else if m.from == pos.pos.castling[side][1] {
+            }
+            if pos.pos.castling[side][1] != 64 {
                 hash ^= zobrist::CASTLING[side][1];
             }
+        } else if m.from == pos.pos.castling[side][0] {
+            hash ^= zobrist::CASTLING[side][0];
+        } else if m.from == pos.pos.castling[side][1] {
+            hash ^= zobrist::CASTLING[side][1];
         }
 
+        let mut dest = m.to;
+
         match m.flags {
-            MoveFlag::CASTLE_LEFT => {
-                if update_hash {
-                    hash ^= zobrist::PIECES[m.to as usize - 2][side][Rook];
-                    hash ^= zobrist::PIECES[m.to as usize + 1][side][Rook];
-                }
-                mg -= MG_PSTS[Rook][flip(m.to - 2, side) as usize];
-                eg -= EG_PSTS[Rook][flip(m.to - 2, side) as usize];
-                mg += MG_PSTS[Rook][flip(m.to + 1, side) as usize];
-                eg += EG_PSTS[Rook][flip(m.to + 1, side) as usize];
-            }
-            MoveFlag::CASTLE_RIGHT => {
-                if update_hash {
-                    hash ^= zobrist::PIECES[m.to as usize + 1][side][Rook];
-                    hash ^= zobrist::PIECES[m.to as usize - 1][side][Rook];
-                }
-                mg -= MG_PSTS[Rook][flip(m.to + 1, side) as usize];
-                eg -= EG_PSTS[Rook][flip(m.to + 1, side) as usize];
-                mg += MG_PSTS[Rook][flip(m.to - 1, side) as usize];
-                eg += EG_PSTS[Rook][flip(m.to - 1, side) as usize];
+            MoveFlag::CASTLE_LEFT | MoveFlag::CASTLE_RIGHT => {
+                let (king_to, rook_to) = m.castling_destinations();
+
+                hash ^= zobrist::PIECES[m.to as usize][side][Rook];
+                hash ^= zobrist::PIECES[rook_to as usize][side][Rook];
+                mg -= MG_PSTS[Rook][flip(m.to, side) as usize];
+                eg -= EG_PSTS[Rook][flip(m.to, side) as usize];
+                mg += MG_PSTS[Rook][flip(rook_to, side) as usize];
+                eg += EG_PSTS[Rook][flip(rook_to, side) as usize];
+                dest = king_to;
             }
             MoveFlag::EP => {
                 let sq = (pos.pos.en_passant as i8
@@ -256,9 +565,7 @@ impl SearchContext<'_> {
                         PieceColor::Black => 8,
                     }) as u8;
 
-                if update_hash {
-                    hash ^= zobrist::PIECES[sq as usize][!side][Pawn];
-                }
+                hash ^= zobrist::PIECES[sq as usize][!side][Pawn];
                 mg += MG_PSTS[Pawn][flip(sq, !side) as usize];
                 eg += EG_PSTS[Pawn][flip(sq, !side) as usize];
                 phase -= PHASE[Pawn];
@@ -277,14 +584,12 @@ impl SearchContext<'_> {
                     eg += EG_PSTS[target][flip(m.to, !side) as usize];
                     phase -= PHASE[target];
 
-                    if update_hash {
-                        hash ^= zobrist::PIECES[m.to as usize][!side][target];
-                        if target == Rook {
-                            if m.to == pos.pos.castling[!side][0] {
-                                hash ^= zobrist::CASTLING[!side][0];
-                            } else if m.to == pos.pos.castling[!side][1] {
-                                hash ^= zobrist::CASTLING[!side][1];
-                            }
+                    hash ^= zobrist::PIECES[m.to as usize][!side][target];
+                    if target == Rook {
+                        if m.to == pos.pos.castling[!side][0] {
+                            hash ^= zobrist::CASTLING[!side][0];
+                        } else if m.to == pos.pos.castling[!side][1] {
+                            hash ^= zobrist::CASTLING[!side][1];
                         }
                     }
                 }
@@ -296,31 +601,47 @@ impl SearchContext<'_> {
             }
         }
 
-        mg += MG_PSTS[piece][flip(m.to, side) as usize];
-        eg += EG_PSTS[piece][flip(m.to, side) as usize];
-        if update_hash {
-            hash ^= zobrist::PIECES[m.to as usize][side][piece];
-            hash ^= zobrist::BLACK;
+        mg += MG_PSTS[piece][flip(dest, side) as usize];
+        eg += EG_PSTS[piece][flip(dest, side) as usize];
+        hash ^= zobrist::PIECES[dest as usize][side][piece];
+        hash ^= zobrist::BLACK;
+        if push_repetition {
             self.repetitions.push(hash);
         }
 
         let mobility = pos.mobility[!pos.pos.side] - pos.mobility[pos.pos.side];
 
-        return SearchPosition {
+        SearchPosition {
             pos: pos.pos.make_move(m),
+            hash,
             eval: (-mg * phase.min(24) + -eg * (24 - phase.min(24))) / 24
                 + mobility.abs().max(1).checked_ilog2().unwrap() as i32 * mobility.signum(),
             mg_eval: -mg,
             eg_eval: -eg,
             phase,
             mobility: pos.mobility,
-        };
+        }
     }
 
     fn hash(&self) -> u64 {
         *self.repetitions.last().unwrap()
     }
 
+    fn skip_root_move(&self, m: Move) -> bool {
+        self.excluded.contains(&m)
+            || (!self.searchmoves.is_empty() && !self.searchmoves.contains(&m))
+    }
+
+    fn update_pv(&mut self, ply: u8, m: Move) {
+        let ply = ply as usize;
+        let child_len = self.pv_len[ply + 1];
+
+        self.pv[ply][0] = m;
+        let (parent, child) = self.pv.split_at_mut(ply + 1);
+        parent[ply][1..=child_len].copy_from_slice(&child[0][..child_len]);
+        self.pv_len[ply] = child_len + 1;
+    }
+
     fn negamax(
         &mut self,
         pos: &SearchPosition,
@@ -331,6 +652,8 @@ impl SearchContext<'_> {
         is_nm: bool,
     ) -> Option<(Move, i32)> {
         let pv_node = beta - alpha != 1;
+        self.pv_len[ply as usize] = 0;
+        self.seldepth = self.seldepth.max(ply);
 
         let in_check = pos.pos.in_check(pos.pos.side);
         if in_check && depth < 3 {
@@ -343,15 +666,22 @@ impl SearchContext<'_> {
         let mut n_moves = 0;
 
         let mut best = (Move::NULL, alpha);
-        let (tt_move, tt_eval, tt_depth, tt_ty) = self.tt.lookup(self.hash()).unwrap_or_default();
+        let tt_entry = self.tt.lookup(self.hash()).unwrap_or_default();
+        let (tt_move, tt_eval, tt_depth, tt_ty) = (
+            tt_entry.best,
+            score_from_tt(tt_entry.eval, ply),
+            tt_entry.depth,
+            tt_entry.ty,
+        );
 
         if tt_depth >= depth
             && (tt_ty == TTEntryType::Exact
                 || (tt_ty == TTEntryType::Lower && tt_eval >= beta)
                 || (tt_ty == TTEntryType::Upper && alpha >= tt_eval))
             && alpha + 1 == beta
+            && (tt_move == Move::NULL
+                || pos.pos.is_pseudolegal(tt_move) && pos.pos.is_legal(tt_move))
         {
-            // TODO: check with is_pseudolegal
             return Some((tt_move, tt_eval));
         }
 
@@ -381,19 +711,23 @@ impl SearchContext<'_> {
             return Some((Move::NULL, pos.eval));
         }
 
-        let mut skip_quiets = false;
-
         let mut search_pv = true;
 
-        let moves = movegen::<true>(&pos.pos);
-        let move_count = moves.len();
+        let move_count = mobility(&pos.pos);
+        let mut picker = self.movepicker(pos, tt_move, ply);
 
-        for m in self.movepicker(moves, &pos, tt_move, ply) {
-            if !m.capture()
+        while let Some(m) = picker.next_move(self, pos) {
+            if ply == 0 && self.skip_root_move(m) {
+                continue;
+            }
+
+            if !pv_node
+                && !in_check
+                && !m.capture()
                 && m.promotion() == PieceType::None
-                && skip_quiets
-                && !self.killers[ply as usize].contains(&m)
-            // && self.cmh[prev_move.from as usize][prev_move.to as usize] != m
+                && depth <= SEE_QUIET_DEPTH
+                && n_moves > 0
+                && !pos.pos.see(m, -SEE_QUIET_MARGIN * depth as i32)
             {
                 continue;
             }
@@ -404,8 +738,18 @@ impl SearchContext<'_> {
             if !pos.pos.in_check(!pos.pos.side) {
                 n_moves += 1;
 
+                if ply == 0 && self.timeman.start.elapsed() >= CURRMOVE_DELAY {
+                    self.reporter.currmove(depth, m, n_moves);
+                }
+
                 self.nodes += 1;
-                if self.nodes % 2048 == 0 && self.timeman.hard_stop() && !(ply == 0 && depth == 1) {
+                if self.nodes - self.flushed_nodes >= NODES_FLUSH {
+                    self.flush_nodes();
+                }
+                if ((self.nodes % 2048 == 0 && self.timeman.hard_stop())
+                    || self.timeman.node_stop(self.total_nodes()))
+                    && !(ply == 0 && depth == 1)
+                {
                     return None;
                 }
 
@@ -418,7 +762,7 @@ impl SearchContext<'_> {
                     && pos.eval + FP_BASE + FP_MUL * depth as i32 <= alpha
                     && best.1 > -CHECKMATE
                 {
-                    skip_quiets = true;
+                    picker.skip_quiets();
                 }
 
                 if best.1 > -CHECKMATE
@@ -427,39 +771,37 @@ impl SearchContext<'_> {
                     && depth <= LMP_DEPTH
                     && n_moves >= LMP_BASE + LMP_MUL * (depth as u16).pow(LMP_POW)
                 {
-                    skip_quiets = true;
+                    picker.skip_quiets();
                 }
 
                 let res = if self.is_draw(&pos.pos) {
+                    self.pv_len[ply as usize + 1] = 0;
                     Some((Move::NULL, 0))
+                } else if search_pv {
+                    self.move_stack[ply as usize] = m;
+                    self.negamax(&pos, -beta, -best.1, ply + 1, depth - 1, is_nm)
                 } else {
-                    if search_pv {
-                        self.move_stack[ply as usize] = m;
-                        self.negamax(&pos, -beta, -best.1, ply + 1, depth - 1, is_nm)
+                    let red = if !m.capture()
+                        && beta - alpha == 1
+                        && n_moves >= LMR_NMOVES
+                        && depth > 1
+                    {
+                        lmr_reduction.clamp(1, depth - 1)
                     } else {
-                        let red = if !m.capture()
-                            && beta - alpha == 1
-                            && n_moves >= LMR_NMOVES
-                            && depth > 1
-                        {
-                            lmr_reduction.clamp(1, depth - 1)
-                        } else {
-                            1
-                        };
-
-                        let rdepth = depth - red;
-
-                        self.move_stack[ply as usize] = m;
-                        let mut res =
-                            self.negamax(&pos, -best.1 - 1, -best.1, ply + 1, rdepth, is_nm);
-                        if let Some(r) = res {
-                            if -r.1 > best.1 {
-                                res = self.negamax(&pos, -beta, -best.1, ply + 1, depth - 1, is_nm);
-                            }
-                        }
+                        1
+                    };
 
-                        res
+                    let rdepth = depth - red;
+
+                    self.move_stack[ply as usize] = m;
+                    let mut res = self.negamax(&pos, -best.1 - 1, -best.1, ply + 1, rdepth, is_nm);
+                    if let Some(r) = res {
+                        if -r.1 > best.1 {
+                            res = self.negamax(&pos, -beta, -best.1, ply + 1, depth - 1, is_nm);
+                        }
                     }
+
+                    res
                 };
 
                 if let Some(res) = res {
@@ -467,6 +809,12 @@ impl SearchContext<'_> {
                     if -res.1 > best.1 {
                         best.0 = m;
                         best.1 = best.1.max(-res.1);
+                        if ply == 0 && self.excluded.is_empty() {
+                            self.reporter.best_move(depth, m, best.1);
+                        }
+                        if pv_node {
+                            self.update_pv(ply, m);
+                        }
                         if -res.1 >= beta {
                             if m.flags == MoveFlag::QUIET && self.killers[ply as usize][0] != m {
                                 self.killers[ply as usize][1] = self.killers[ply as usize][0];
@@ -475,6 +823,10 @@ impl SearchContext<'_> {
                                     depth as i32 * HIST_MUL + HIST_ADD;
                                 if ply > 0 {
                                     let prev_move = self.move_stack[ply as usize - 1];
+                                    if prev_move != Move::NULL {
+                                        self.counters[!pos.pos.side][prev_move.piece]
+                                            [prev_move.to as usize] = m;
+                                    }
                                     self.continuations[0][!pos.pos.side][m.piece][m.to as usize]
                                         [prev_move.piece]
                                         [prev_move.to as usize] +=
@@ -510,12 +862,6 @@ impl SearchContext<'_> {
             }
         }
 
-        let mut tt_score = best.1;
-        if tt_score >= CHECKMATE {
-            tt_score -= ply as i32;
-        } else if tt_score <= -CHECKMATE {
-            tt_score += ply as i32;
-        }
         let ty = if best.1 >= beta {
             TTEntryType::Lower
         } else if best.1 <= alpha {
@@ -524,14 +870,17 @@ impl SearchContext<'_> {
             TTEntryType::Exact
         };
 
-        self.tt.insert(TTEntry::new(
-            self.hash(),
-            tt_score,
-            best.0,
-            depth,
-            self.tt_age,
-            ty,
-        ));
+        // Root results with restricted moves are not the true score of the position
+        if ply > 0 || (self.excluded.is_empty() && self.searchmoves.is_empty()) {
+            self.tt.insert(TTEntry::new(
+                self.hash(),
+                score_to_tt(best.1, ply),
+                best.0,
+                depth,
+                self.tt_age,
+                ty,
+            ));
+        }
 
         Some(best)
     }
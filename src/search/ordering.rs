@@ -1,5 +1,5 @@
 use crate::{
-    movegen::MoveList,
+    movegen::{movegen, quiet_movegen, MoveList},
     position::{Move, MoveFlag, PieceType},
 };
 
@@ -17,93 +17,217 @@ impl SearchContext<'_> {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    TTMove,
+    GenerateCaptures,
+    GoodCaptures,
+    Killers,
+    CounterMove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Yields the moves of a node in stages, only generating and scoring the captures and quiets
+/// once the earlier stages failed to produce a cutoff
 pub struct MovePicker {
-    moves: MoveList,
-    scores: Vec<i32>,
+    stage: Stage,
+    tt_move: Move,
+    killers: [Move; 2],
+    killer_idx: usize,
+    counter: Move,
+    captures: MoveList,
+    capture_scores: [i32; 256],
+    quiets: MoveList,
+    quiet_scores: [i32; 256],
+    skip_quiets: bool,
+    ply: u8,
 }
 
+/// Scores of losing captures are offset below every good capture
+const BAD_CAPTURE: i32 = -1 << 20;
+
 impl SearchContext<'_> {
-    pub(super) fn movepicker(
-        &self,
-        moves: MoveList,
-        pos: &SearchPosition,
-        pv: Move,
-        ply: u8,
-    ) -> MovePicker {
-        let mut scores = vec![];
-
-        for m in &moves {
-            scores.push(self.move_score(*m, pos, pv, ply))
+    pub(super) fn movepicker(&self, pos: &SearchPosition, tt_move: Move, ply: u8) -> MovePicker {
+        let counter = if ply > 0 {
+            let prev = self.move_stack[ply as usize - 1];
+            self.counters[pos.pos.side][prev.piece][prev.to as usize]
+        } else {
+            Move::NULL
+        };
+
+        MovePicker {
+            stage: if tt_move == Move::NULL {
+                Stage::GenerateCaptures
+            } else {
+                Stage::TTMove
+            },
+            tt_move,
+            killers: self.killers[ply as usize],
+            killer_idx: 0,
+            counter,
+            captures: MoveList::new(),
+            capture_scores: [0; 256],
+            quiets: MoveList::new(),
+            quiet_scores: [0; 256],
+            skip_quiets: false,
+            ply,
         }
-
-        MovePicker { scores, moves }
     }
 
-    fn move_score(&self, m: Move, pos: &SearchPosition, pv: Move, ply: u8) -> i32 {
-        if m == pv {
+    fn quiet_score(&self, m: Move, pos: &SearchPosition, ply: u8) -> i32 {
+        if m.promotion() != PieceType::None {
             return i32::MAX;
         }
-        if m.capture() && m.flags != MoveFlag::EP {
-            return i32::MAX - 120 + MVVLVA_LOOKUP[m.piece][pos.pos.piece_at(m.to).ty];
-        }
 
-        if self.killers[ply as usize].contains(&m) {
-            return i32::MAX - 112;
-        }
+        let mut score = self.history[pos.pos.side][m.piece][m.to as usize];
+        score -= MG_PSTS[m.piece][flip(m.from, pos.pos.side) as usize];
+        score += MG_PSTS[m.piece][flip(m.to, pos.pos.side) as usize];
 
-        if m.promotion() != PieceType::None {
-            return i32::MAX - 130;
+        if ply > 0 {
+            let prev = self.move_stack[ply as usize - 1];
+            score += self.continuations[0][pos.pos.side][m.piece][m.to as usize][prev.piece]
+                [prev.to as usize];
         }
-
-        if m.flags == MoveFlag::QUIET {
-            let mut score = self.history[pos.pos.side][m.piece][m.to as usize];
-            score -= MG_PSTS[m.piece][flip(m.from, pos.pos.side) as usize];
-            score += MG_PSTS[m.piece][flip(m.to, pos.pos.side) as usize];
-
-            if ply > 0 {
-                let prev = self.move_stack[ply as usize - 1];
-                score += self.continuations[0][pos.pos.side][m.piece][m.to as usize][prev.piece]
-                    [prev.to as usize];
-            }
-            if ply > 1 {
-                let prev = self.move_stack[ply as usize - 2];
-                score += self.continuations[1][pos.pos.side][m.piece][m.to as usize][prev.piece]
-                    [prev.to as usize];
-            }
-
-            return score;
+        if ply > 1 {
+            let prev = self.move_stack[ply as usize - 2];
+            score += self.continuations[1][pos.pos.side][m.piece][m.to as usize][prev.piece]
+                [prev.to as usize];
         }
 
-        0
+        score
     }
 }
 
-impl Iterator for MovePicker {
-    type Item = Move;
+impl MovePicker {
+    /// Stops yielding quiet moves other than promotions, including killers and the counter move
+    pub(super) fn skip_quiets(&mut self) {
+        self.skip_quiets = true;
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.moves.len == 0 {
-            return None;
+    pub(super) fn next_move(&mut self, ctx: &SearchContext, pos: &SearchPosition) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TTMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if pos.pos.is_pseudolegal(self.tt_move) {
+                        return Some(self.tt_move);
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    self.captures = movegen::<false>(&pos.pos);
+                    for i in 0..self.captures.len as usize {
+                        let m = self.captures.moves[i];
+                        let mvvlva = mvvlva(m, pos);
+
+                        self.capture_scores[i] = if pos.pos.see(m, 0) {
+                            mvvlva
+                        } else {
+                            BAD_CAPTURE + mvvlva
+                        };
+                    }
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    match pick(&mut self.captures, &mut self.capture_scores, |score| {
+                        score > BAD_CAPTURE / 2
+                    }) {
+                        Some(m) if m == self.tt_move => {}
+                        Some(m) => return Some(m),
+                        None => self.stage = Stage::Killers,
+                    }
+                }
+                Stage::Killers => {
+                    if self.skip_quiets || self.killer_idx == self.killers.len() {
+                        // Untried killers must not be filtered out of the promotions later
+                        self.killers[self.killer_idx..].fill(Move::NULL);
+                        self.stage = Stage::CounterMove;
+                        continue;
+                    }
+
+                    let killer = self.killers[self.killer_idx];
+                    self.killer_idx += 1;
+
+                    if killer != self.tt_move && pos.pos.is_pseudolegal(killer) {
+                        return Some(killer);
+                    }
+                }
+                Stage::CounterMove => {
+                    self.stage = Stage::GenerateQuiets;
+
+                    let counter = self.counter;
+                    if self.skip_quiets {
+                        self.counter = Move::NULL;
+                    } else if counter != self.tt_move
+                        && !self.killers.contains(&counter)
+                        && pos.pos.is_pseudolegal(counter)
+                    {
+                        return Some(counter);
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    self.quiets = quiet_movegen(&pos.pos);
+                    for i in 0..self.quiets.len as usize {
+                        self.quiet_scores[i] = ctx.quiet_score(self.quiets.moves[i], pos, self.ply);
+                    }
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    let skip_quiets = self.skip_quiets;
+
+                    match pick(&mut self.quiets, &mut self.quiet_scores, |score| {
+                        !skip_quiets || score == i32::MAX
+                    }) {
+                        Some(m)
+                            if m == self.tt_move
+                                || self.killers.contains(&m)
+                                || m == self.counter => {}
+                        Some(m) => return Some(m),
+                        None => self.stage = Stage::BadCaptures,
+                    }
+                }
+                Stage::BadCaptures => {
+                    match pick(&mut self.captures, &mut self.capture_scores, |_| true) {
+                        Some(m) if m == self.tt_move => {}
+                        Some(m) => return Some(m),
+                        None => self.stage = Stage::Done,
+                    }
+                }
+                Stage::Done => return None,
+            }
         }
+    }
+}
 
-        let mut best = 0;
-        let mut idx = 0;
+/// Removes and returns the highest scored move, as long as its score is accepted by `accept`
+fn pick(
+    moves: &mut MoveList,
+    scores: &mut [i32; 256],
+    accept: impl Fn(i32) -> bool,
+) -> Option<Move> {
+    if moves.len == 0 {
+        return None;
+    }
 
-        for (i, &score) in self.scores.iter().enumerate() {
-            if score > best {
-                idx = i;
-                best = score;
-            }
+    let mut idx = 0;
+    for i in 1..moves.len as usize {
+        if scores[i] > scores[idx] {
+            idx = i;
         }
+    }
 
-        self.moves.len -= 1;
-        let len = self.moves.len as usize;
-        self.scores.swap(idx, len);
-        self.moves.moves.swap(idx, len);
-        self.scores.pop();
-
-        Some(self.moves.moves[len])
+    if !accept(scores[idx]) {
+        return None;
     }
+
+    moves.len -= 1;
+    let len = moves.len as usize;
+    scores.swap(idx, len);
+    moves.moves.swap(idx, len);
+
+    Some(moves.moves[len])
 }
 
 fn mvvlva(m: Move, pos: &SearchPosition) -> i32 {
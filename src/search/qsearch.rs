@@ -4,6 +4,10 @@ use super::{SearchContext, SearchPosition};
 
 impl SearchContext<'_> {
     pub fn qsearch(&mut self, pos: &SearchPosition, ply: u8, mut alpha: i32, beta: i32) -> i32 {
+        self.seldepth = self.seldepth.max(ply);
+
+        // The transposition table is left to the main search, probing and storing here cost more
+        // than the cutoffs it saved
         let eval = pos.eval;
         if eval >= beta {
             return beta;
@@ -17,16 +21,21 @@ impl SearchContext<'_> {
 
         let captures = movegen::<false>(&pos.pos);
         for m in &self.order_mvvlva(captures, pos) {
-            let pos = self.update(&pos, *m, false);
-            if !pos.pos.in_check(!pos.pos.side) {
+            if !pos.pos.see(*m, 0) {
+                continue;
+            }
+
+            // Captures are irreversible, so the position can't repeat further down
+            let child = self.update(pos, *m, false);
+            if !child.pos.in_check(!child.pos.side) {
                 self.nodes += 1;
-                let eval = -self.qsearch(&pos, ply + 1, -beta, -alpha);
+                let score = -self.qsearch(&child, ply + 1, -beta, -alpha);
 
-                if eval >= beta {
+                if score >= beta {
                     return beta;
                 }
-                if eval > alpha {
-                    alpha = eval;
+                if score > alpha {
+                    alpha = score;
                 }
             }
         }
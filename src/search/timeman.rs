@@ -1,4 +1,10 @@
-use std::time::{Duration, Instant};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{position::PieceColor, SearchOptions};
 
@@ -8,6 +14,10 @@ pub struct TimeManager {
     pub start: Instant,
     pub hard: Option<Duration>,
     pub soft: Option<Duration>,
+    pub nodes: Option<u64>,
+    pub stop: Arc<AtomicBool>,
+    pub ponder: Arc<AtomicBool>,
+    pondering: bool,
 }
 
 impl TimeManager {
@@ -17,7 +27,12 @@ impl TimeManager {
             PieceColor::Black => (options.btime, options.binc),
         };
         let (hard, soft) = {
-            if t == i64::MAX {
+            if options.infinite {
+                (None, None)
+            } else if let Some(movetime) = options.movetime {
+                let movetime = Duration::from_millis(movetime);
+                (Some(movetime), Some(movetime))
+            } else if t == i64::MAX {
                 (None, None)
             } else {
                 let t = t as f32;
@@ -47,17 +62,36 @@ impl TimeManager {
             start: Instant::now(),
             hard,
             soft,
+            nodes: options.nodes,
+            stop: options.stop.clone(),
+            ponder: options.ponder.clone(),
+            pondering: options.ponder.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns whether the search is still pondering, the clock starts at `ponderhit`.
+    fn pondering(&mut self) -> bool {
+        if self.pondering && !self.ponder.load(Ordering::Relaxed) {
+            self.pondering = false;
+            self.start = Instant::now();
         }
+        self.pondering
     }
 
-    pub fn hard_stop(&self) -> bool {
-        if self.hard.is_none() {
+    pub fn hard_stop(&mut self) -> bool {
+        if self.stop.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.pondering() || self.hard.is_none() {
             return false;
         }
         self.start.elapsed() > self.hard.unwrap()
     }
-    pub fn soft_stop(&self) -> bool {
-        if self.soft.is_none() {
+    pub fn node_stop(&self, nodes: u64) -> bool {
+        self.nodes.is_some_and(|limit| nodes >= limit)
+    }
+    pub fn soft_stop(&mut self) -> bool {
+        if self.pondering() || self.soft.is_none() {
             return false;
         }
         self.start.elapsed() > self.soft.unwrap()
@@ -1,8 +1,30 @@
-use std::mem;
+use std::{
+    mem,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use crate::position::Move;
+use crate::position::{Move, PieceType};
 
-pub struct TT(Vec<TTEntry>, usize, usize);
+/// Transposition table shared between search threads without locking.
+///
+/// Every entry is two words, the data word and the key word. The key word holds
+/// the upper 32 bits of the hash and the remaining entry fields, XORed with a
+/// multiplicative mix of the data word. Every bit of the data word reaches the
+/// hash bits that way, so a torn write between two threads fails verification
+/// on lookup.
+pub struct TT(Vec<TTBucket>, usize);
+
+const BUCKET_SIZE: usize = 4;
+
+#[repr(align(64))]
+#[derive(Default)]
+struct TTBucket([TTSlot; BUCKET_SIZE]);
+
+#[derive(Default)]
+struct TTSlot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
 
 #[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub struct TTEntry {
@@ -16,7 +38,7 @@ pub struct TTEntry {
 
 impl TT {
     pub fn new(size: usize) -> Self {
-        Self(vec![TTEntry::default(); tt_size(size)], 0, size)
+        Self(buckets(tt_size(size)), size)
     }
 
     pub fn new_default() -> Self {
@@ -24,52 +46,163 @@ impl TT {
     }
 
     pub fn set_size(&mut self, size: usize) {
-        self.0 = vec![TTEntry::default(); tt_size(size)];
-        self.1 = 0;
-        self.2 = size;
+        self.0 = buckets(tt_size(size));
+        self.1 = size;
     }
 
     pub fn size(&self) -> usize {
-        self.2
+        self.1
     }
 
-    pub fn clear(&mut self) {
-        self.0.fill(TTEntry::default());
-        self.1 = 0;
+    pub fn clear(&self) {
+        for bucket in &self.0 {
+            for slot in &bucket.0 {
+                slot.key.store(0, Ordering::Relaxed);
+                slot.data.store(0, Ordering::Relaxed);
+            }
+        }
     }
 
-    pub fn hashfull(&self) -> usize {
-        (self.1 * 1000) / self.0.len()
+    /// Permill of sampled entries written during the search with the given age.
+    pub fn hashfull(&self, age: u16) -> usize {
+        let sample = &self.0[..self.0.len().min(1000 / BUCKET_SIZE)];
+        let used = sample
+            .iter()
+            .flat_map(|bucket| &bucket.0)
+            .filter(|slot| slot.load().is_some_and(|(_, entry)| entry.age == age))
+            .count();
+
+        used * 1000 / (sample.len() * BUCKET_SIZE)
     }
 
-    pub fn insert(&mut self, index: TTEntry) {
-        let i = index.hash as usize % self.0.len();
-        let old_index = &mut self.0[i];
-        if score(old_index.age, old_index.depth) <= score(index.age, index.depth) {
-            if old_index.depth == 0 {
-                self.1 += 1;
+    pub fn insert(&self, mut entry: TTEntry) {
+        let bucket = &self.0[entry.hash as usize % self.0.len()];
+        let key = (entry.hash >> 32) as u32;
+
+        let mut replace = 0;
+        let mut replace_score = i32::MAX;
+
+        for (i, slot) in bucket.0.iter().enumerate() {
+            let Some((slot_key, old)) = slot.load() else {
+                replace = i;
+                break;
+            };
+
+            if slot_key == key {
+                // Keep deeper results of the current search unless the new one is exact
+                if entry.ty != TTEntryType::Exact
+                    && old.age == entry.age
+                    && old.depth > entry.depth.saturating_add(TT_DEPTH_MARGIN)
+                {
+                    return;
+                }
+                if entry.best == Move::NULL {
+                    entry.best = old.best;
+                }
+
+                replace = i;
+                break;
+            }
+
+            let score = old.depth as i32 - TT_AGE_WEIGHT * entry.age.wrapping_sub(old.age) as i32;
+            if score < replace_score {
+                replace = i;
+                replace_score = score;
             }
-            *old_index = index;
         }
+
+        bucket.0[replace].store(key, entry);
+    }
+
+    pub fn lookup(&self, hash: u64) -> Option<TTEntry> {
+        let bucket = &self.0[hash as usize % self.0.len()];
+        let key = (hash >> 32) as u32;
+
+        bucket.0.iter().find_map(|slot| {
+            let (slot_key, entry) = slot.load()?;
+            (slot_key == key).then_some(TTEntry { hash, ..entry })
+        })
     }
+}
+
+const TT_DEPTH_MARGIN: u8 = 2;
+const TT_AGE_WEIGHT: i32 = 8;
+
+// Distinguishes stored entries from empty slots
+const OCCUPIED: u32 = 1 << 29;
+
+// Odd, so every bit of the data word changes the upper half of the mix
+const DATA_MIX: u64 = 0x9e3779b97f4a7c15;
+
+impl TTSlot {
+    fn load(&self) -> Option<(u32, TTEntry)> {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed) ^ data.wrapping_mul(DATA_MIX);
+        let extra = key as u32;
 
-    pub fn lookup(&mut self, hash: u64) -> Option<(Move, i32, u8, TTEntryType)> {
-        let i = hash as usize % self.0.len();
-        let index = &self.0[i];
-        if index.hash == hash && index.depth > 0 {
-            Some((index.best, index.eval, index.depth, index.ty))
-        } else {
-            None
+        if extra & OCCUPIED == 0 {
+            return None;
         }
+
+        let entry = TTEntry {
+            hash: 0,
+            eval: data as u32 as i32,
+            best: Move::new(
+                (data >> 48) as u8 & 0x3f,
+                (data >> 54) as u8 & 0x3f,
+                (data >> 60) as u8,
+                PIECE_TYPES[(extra >> 24) as usize & 0x7],
+            ),
+            depth: extra as u8,
+            age: (extra >> 8) as u16,
+            ty: match extra >> 27 & 0x3 {
+                1 => TTEntryType::Upper,
+                2 => TTEntryType::Lower,
+                _ => TTEntryType::Exact,
+            },
+        };
+
+        Some(((key >> 32) as u32, entry))
+    }
+
+    fn store(&self, key: u32, entry: TTEntry) {
+        let m = entry.best;
+
+        let data = entry.eval as u32 as u64
+            | (m.from as u64) << 48
+            | (m.to as u64) << 54
+            | (m.flags as u64) << 60;
+        let extra = entry.depth as u32
+            | (entry.age as u32) << 8
+            | (m.piece as u32) << 24
+            | (entry.ty as u32) << 27
+            | OCCUPIED;
+
+        self.key.store(
+            ((key as u64) << 32 | extra as u64) ^ data.wrapping_mul(DATA_MIX),
+            Ordering::Relaxed,
+        );
+        self.data.store(data, Ordering::Relaxed);
     }
 }
 
-fn score(age: u16, depth: u8) -> u16 {
-    age + depth as u16 / 3
+const PIECE_TYPES: [PieceType; 8] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+    PieceType::None,
+    PieceType::None,
+];
+
+fn buckets(n: usize) -> Vec<TTBucket> {
+    (0..n.max(1)).map(|_| TTBucket::default()).collect()
 }
 
 fn tt_size(size: usize) -> usize {
-    size * 1000000 / mem::size_of::<TTEntry>()
+    size * 1000000 / mem::size_of::<TTBucket>()
 }
 
 impl TTEntry {
@@ -91,3 +224,47 @@ pub enum TTEntryType {
     Upper,
     Lower,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{TTEntry, TTEntryType, TTSlot};
+    use crate::position::{Move, MoveFlag, PieceType};
+
+    fn entry(eval: i32, depth: u8) -> TTEntry {
+        TTEntry::new(
+            0,
+            eval,
+            Move::new(12, 28, MoveFlag::DOUBLE_PAWN, PieceType::Pawn),
+            depth,
+            300,
+            TTEntryType::Lower,
+        )
+    }
+
+    #[test]
+    fn pack_unpack() {
+        let slot = TTSlot::default();
+        slot.store(0xdeadbeef, entry(-1234, 0));
+
+        assert!(slot.load() == Some((0xdeadbeef, entry(-1234, 0))));
+        assert!(TTSlot::default().load().is_none());
+    }
+
+    #[test]
+    fn torn_write() {
+        use std::sync::atomic::Ordering;
+
+        // Two threads storing the same position and move with different scores and depths
+        let (a, b) = (TTSlot::default(), TTSlot::default());
+        a.store(0xdeadbeef, entry(17, 9));
+        b.store(0xdeadbeef, entry(20, 3));
+
+        let torn = TTSlot::default();
+        torn.key
+            .store(a.key.load(Ordering::Relaxed), Ordering::Relaxed);
+        torn.data
+            .store(b.data.load(Ordering::Relaxed), Ordering::Relaxed);
+
+        assert!(torn.load().map_or(true, |(key, _)| key != 0xdeadbeef));
+    }
+}
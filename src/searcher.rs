@@ -0,0 +1,166 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex,
+};
+
+use crate::{
+    position::{Move, Position},
+    search::{search, tt::TT, NullReporter, SearchReporter, SearchResult},
+    SearchOptions,
+};
+
+/// Limits of a single search, unset limits don't constrain it. Without any limit the search runs
+/// until [`Searcher::stop`].
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// Remaining clock time of white in milliseconds
+    pub wtime: Option<i64>,
+    /// Remaining clock time of black in milliseconds
+    pub btime: Option<i64>,
+    pub winc: u64,
+    pub binc: u64,
+    pub movestogo: Option<u64>,
+    pub depth: Option<u8>,
+    /// Exact time to search in milliseconds
+    pub movetime: Option<u64>,
+    pub nodes: Option<u64>,
+    /// Stop once a mate in this many moves is found
+    pub mate: Option<u8>,
+    /// Search until [`Searcher::stop`] even if other limits are set
+    pub infinite: bool,
+    /// Restrict the root to these moves
+    pub searchmoves: Vec<Move>,
+}
+
+/// A reusable engine instance. It owns the transposition table and the settings shared by all
+/// searches, and can be cloned to stop or query a search running on another thread.
+#[derive(Clone)]
+pub struct Searcher {
+    tt: Arc<TT>,
+    threads: usize,
+    multipv: usize,
+    reporter: Arc<dyn SearchReporter>,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+    /// Notified on [`Searcher::stop`] and [`Searcher::ponderhit`]
+    signal: Arc<(Mutex<()>, Condvar)>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Searcher {
+    /// A single threaded searcher with a 16 MB transposition table
+    pub fn new() -> Self {
+        Self {
+            tt: Arc::new(TT::new_default()),
+            threads: 1,
+            multipv: 1,
+            reporter: Arc::new(NullReporter),
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::new(AtomicBool::new(false)),
+            signal: Arc::new((Mutex::new(()), Condvar::new())),
+        }
+    }
+
+    /// Resizes the transposition table to `size` MB (10^6 bytes), clearing it
+    pub fn set_hash(&mut self, size: usize) {
+        self.tt = Arc::new(TT::new(size));
+    }
+
+    pub fn hash(&self) -> usize {
+        self.tt.size()
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn set_multipv(&mut self, multipv: usize) {
+        self.multipv = multipv.max(1);
+    }
+
+    /// Sets the receiver of the progress of all following searches, nothing is reported by
+    /// default
+    pub fn set_reporter(&mut self, reporter: impl SearchReporter + 'static) {
+        self.reporter = Arc::new(reporter);
+    }
+
+    /// Forgets everything learned in previous searches
+    pub fn clear(&self) {
+        self.tt.clear();
+    }
+
+    /// Clears the stop signal and sets whether the next search starts pondering. Needed before
+    /// searching again after [`Searcher::stop`], and before spawning the search thread so a stop
+    /// or ponderhit arriving early is not lost.
+    pub fn reset_signals(&self, ponder: bool) {
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(ponder, Ordering::Relaxed);
+    }
+
+    /// Ends the running search as soon as possible, it still returns its best move
+    pub fn stop(&self) {
+        let _guard = self.signal.0.lock().unwrap();
+        self.stop.store(true, Ordering::Relaxed);
+        self.signal.1.notify_all();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Switches a pondering search to normal time management
+    pub fn ponderhit(&self) {
+        let _guard = self.signal.0.lock().unwrap();
+        self.ponder.store(false, Ordering::Relaxed);
+        self.signal.1.notify_all();
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.ponder.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until [`Searcher::stop`], or until [`Searcher::ponderhit`] unless `infinite`. Used
+    /// to hold back the best move of a finished `go infinite` or `go ponder` search.
+    pub fn wait(&self, infinite: bool) {
+        let (lock, condvar) = &*self.signal;
+        let mut guard = lock.lock().unwrap();
+
+        while (infinite || self.is_pondering()) && !self.is_stopped() {
+            guard = condvar.wait(guard).unwrap();
+        }
+    }
+
+    /// Searches `pos`. `history` holds the hashes of the positions of the game up to and
+    /// including `pos`, to detect repetitions.
+    pub fn search(&self, pos: &Position, history: &[u64], limits: Limits) -> SearchResult {
+        let options = SearchOptions {
+            wtime: limits.wtime.unwrap_or(i64::MAX),
+            btime: limits.btime.unwrap_or(i64::MAX),
+            winc: limits.winc,
+            binc: limits.binc,
+            movestogo: limits.movestogo,
+            depth: limits.depth.unwrap_or(255),
+            movetime: limits.movetime,
+            nodes: limits.nodes,
+            mate: limits.mate,
+            infinite: limits.infinite,
+            threads: self.threads,
+            multipv: self.multipv,
+            searchmoves: limits.searchmoves,
+            reporter: self.reporter.clone(),
+            stop: self.stop.clone(),
+            ponder: self.ponder.clone(),
+        };
+
+        search(pos, options, history.to_vec(), &self.tt)
+    }
+}